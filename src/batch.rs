@@ -0,0 +1,201 @@
+//! Many keys at once, at most `max_pool_connections` requests in flight
+//! (17 by default, the pool size of the python client).
+//!
+use std::future::Future;
+
+use bytes::Bytes;
+use futures::stream::{self, StreamExt};
+
+use crate::client::Client;
+use crate::error::{Error, Kind, Result};
+use crate::response::Response;
+
+/// The result for each key of a batch, in the order the keys were given.
+/// One failed key does not stop the others.
+#[derive(Debug)]
+pub struct Batch<T> {
+    results: Vec<(String, Result<T>)>,
+}
+
+impl<T> Batch<T> {
+    pub fn len(&self) -> usize {
+        self.results.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.results.is_empty()
+    }
+    /// True when every key succeeded
+    pub fn is_ok(&self) -> bool {
+        self.results.iter().all(|(_, result)| result.is_ok())
+    }
+    /// The result of the key (the first, when it was given twice)
+    pub fn get(&self, key: &str) -> Option<&Result<T>> {
+        self.results
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, result)| result)
+    }
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Result<T>)> {
+        self.results
+            .iter()
+            .map(|(key, result)| (key.as_str(), result))
+    }
+    pub fn failures(&self) -> impl Iterator<Item = (&str, &Error)> {
+        self.results
+            .iter()
+            .filter_map(|(key, result)| result.as_ref().err().map(|e| (key.as_str(), e)))
+    }
+    pub fn into_results(self) -> Vec<(String, Result<T>)> {
+        self.results
+    }
+
+    /// Every value, or one error that names each failed key. The error has
+    /// the kind of the failures when they share one, else `Kind::Request`.
+    pub fn into_result(self) -> Result<Vec<(String, T)>> {
+        let total = self.results.len();
+        let mut values = Vec::with_capacity(total);
+        let mut failures = Vec::new();
+        for (key, result) in self.results {
+            match result {
+                Ok(value) => values.push((key, value)),
+                Err(err) => failures.push((key, err)),
+            }
+        }
+        if failures.is_empty() {
+            return Ok(values);
+        }
+
+        let kind = failures[0].1.kind();
+        let kind = if failures.iter().all(|(_, err)| err.kind() == kind) {
+            *kind
+        } else {
+            Kind::Request
+        };
+        let detail = failures
+            .iter()
+            .map(|(key, err)| format!("{}: {}", key, err))
+            .collect::<Vec<_>>()
+            .join("; ");
+        Err(Error::new(kind, Some(detail)).with_msg(format!(
+            "{} of {} keys failed",
+            failures.len(),
+            total
+        )))
+    }
+}
+
+impl Client {
+    /// Read each file into memory
+    pub async fn get_many<I, K>(&self, keys: I) -> Batch<Bytes>
+    where
+        I: IntoIterator<Item = K>,
+        K: AsRef<str>,
+    {
+        let keys = keys.into_iter().map(|key| (key.as_ref().to_string(), ()));
+        run(keys, self.config.max_pool_connections, |key, ()| {
+            self.read_bytes(key)
+        })
+        .await
+    }
+
+    /// Write each `(key, data)`; every file gets the same content type
+    pub async fn put_many<I, K, B>(&self, items: I, content_type: Option<String>) -> Batch<Response>
+    where
+        I: IntoIterator<Item = (K, B)>,
+        K: AsRef<str>,
+        B: Into<Bytes>,
+    {
+        let items = items
+            .into_iter()
+            .map(|(key, data)| (key.as_ref().to_string(), data.into()));
+        run(
+            items,
+            self.config.max_pool_connections,
+            |key, data: Bytes| self.write(key, data, content_type.clone()),
+        )
+        .await
+    }
+}
+
+/// `f` for each item, with at most `limit` running at once
+async fn run<T, D, F, Fut>(
+    items: impl IntoIterator<Item = (String, D)>,
+    limit: usize,
+    f: F,
+) -> Batch<T>
+where
+    F: Fn(String, D) -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let results = stream::iter(items)
+        .map(|(key, data)| {
+            let fut = f(key.clone(), data);
+            async move {
+                let result = fut.await.map_err(|e| match e.key() {
+                    Some(_) => e,
+                    None => e.with_key(&key),
+                });
+                (key, result)
+            }
+        })
+        .buffered(limit.max(1))
+        .collect()
+        .await;
+    Batch { results }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn limits_concurrency_and_keeps_order() {
+        let running = AtomicUsize::new(0);
+        let peak = AtomicUsize::new(0);
+        let keys = (0..20).map(|i| (format!("k{}", i), i));
+
+        let batch = run(keys, 3, |_, i: u64| {
+            let (running, peak) = (&running, &peak);
+            async move {
+                let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(now, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(20 - i)).await;
+                running.fetch_sub(1, Ordering::SeqCst);
+                Ok(i)
+            }
+        })
+        .await;
+
+        assert_eq!(peak.load(Ordering::SeqCst), 3);
+        let values = batch.into_result().unwrap();
+        assert_eq!(values[19], ("k19".to_string(), 19));
+    }
+
+    #[tokio::test]
+    async fn aggregates_failures() {
+        let keys = ["a", "b", "c"].map(|key| (key.to_string(), ()));
+
+        let batch = run(keys, 17, |key, ()| async move {
+            match key.as_str() {
+                "b" => Ok(()),
+                _ => Err(error::request("refused", "Error reading file")),
+            }
+        })
+        .await;
+
+        assert!(!batch.is_ok());
+        assert!(batch.get("b").unwrap().is_ok());
+        let failed = batch.failures().map(|(key, _)| key).collect::<Vec<_>>();
+        assert_eq!(failed, vec!["a", "c"]);
+        assert_eq!(
+            batch.get("a").unwrap().as_ref().unwrap_err().key(),
+            Some("a")
+        );
+
+        let err = batch.into_result().unwrap_err();
+        assert!(err.to_string().contains("2 of 3 keys failed"), "{}", err);
+    }
+}
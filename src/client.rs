@@ -2,66 +2,151 @@
 use dotenv::dotenv;
 // use eyre::WrapErr;
 
-use aws_sdk_s3::config::{AppName, Region};
+use aws_config::meta::region::RegionProviderChain;
+use aws_sdk_s3::config::timeout::TimeoutConfig;
+use aws_sdk_s3::config::{AppName, Credentials, Region};
 use aws_sdk_s3::Client as S3Client;
 use aws_sdk_s3::Config as S3Config;
+use bytes::Bytes;
 
-use crate::error::{Error, Result};
+use crate::data_file::DataFile;
+use crate::download::ByteRange;
+use crate::error::{self, Result, TimedOut};
+use crate::list::ListOptions;
 use crate::response::{Body, Method, Response};
+use crate::s3_store::S3Store;
+use crate::settings::{Settings, CONFIG_FILE_ENV};
+use crate::store::{GetOptions, ObjectStore, PutOptions};
 use crate::sync_wrapper::SyncWrapper;
 
 use std::fmt;
+use std::path::PathBuf;
+use std::sync::Arc;
 use std::task::{Context, Poll};
+use std::time::Duration;
 use std::{future::Future, pin::Pin};
 
 use tracing::{debug, info};
 use tracing_subscriber::EnvFilter;
 
-/// Async `Client` to make Requests with.
-///
-/// Requests include Method: Read/Write, Filename: String.
-/// Request has a Client.
-///
-/// Default settings with ability to tweak using `Client::builder()`.
-///
 const APP_NAME: &str = "TestAndControl";
 const ETL_OBJ_FILENAME: &str = "etlObj.json";
-const TEST_PROJECT: &str = "fef57333-67c0-4825-9765-5bf48f3d5f63";
+/// The pool size the python client used
+const MAX_POOL_CONNECTIONS: usize = 17;
 
+/// What to send to the `Client`; also the `tower_service::Service` request type.
 #[derive(Debug)]
-pub(crate) struct Request {
+pub struct Request {
     pub(crate) method: Method,
     pub(crate) filename: String,
     pub(crate) content_type: Option<String>,
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) range: Option<ByteRange>,
+    pub(crate) if_match: Option<String>,
+    pub(crate) if_none_match: Option<String>,
 }
 impl Request {
-    pub(crate) fn new(
-        method: Method,
-        filename: impl AsRef<str>,
-        content_type: Option<String>,
-    ) -> Self {
+    pub fn new(method: Method, filename: impl AsRef<str>, content_type: Option<String>) -> Self {
         Self {
             method,
             filename: filename.as_ref().to_string(),
             content_type,
+            timeout: None,
+            range: None,
+            if_match: None,
+            if_none_match: None,
         }
     }
+    /// Read only these bytes of the object
+    pub fn with_range(mut self, range: impl Into<ByteRange>) -> Self {
+        self.range = Some(range.into());
+        self
+    }
+    /// Fail with `Kind::Conflict` unless the object has this ETag
+    pub fn with_if_match(mut self, e_tag: impl Into<String>) -> Self {
+        self.if_match = Some(e_tag.into());
+        self
+    }
+    /// `*` on a `Write`: fail with `Kind::Conflict` when the object exists
+    pub fn with_if_none_match(mut self, e_tag: impl Into<String>) -> Self {
+        self.if_none_match = Some(e_tag.into());
+        self
+    }
+    /// Fail with `Kind::TimedOut` when the response takes longer than
+    /// `timeout` (on top of the client's operation timeout)
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+    #[inline]
+    pub fn method(&self) -> &Method {
+        &self.method
+    }
+    /// The object key, or the key prefix when listing
+    #[inline]
+    pub fn filename(&self) -> &str {
+        &self.filename
+    }
+    #[inline]
+    pub fn content_type(&self) -> Option<&str> {
+        self.content_type.as_deref()
+    }
+    #[inline]
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+    #[inline]
+    pub fn range(&self) -> Option<ByteRange> {
+        self.range
+    }
+    #[inline]
+    pub fn if_match(&self) -> Option<&str> {
+        self.if_match.as_deref()
+    }
+    #[inline]
+    pub fn if_none_match(&self) -> Option<&str> {
+        self.if_none_match.as_deref()
+    }
+
+    /// A copy to send again; `None` when the body cannot be replayed
+    pub fn try_clone(&self) -> Option<Request> {
+        let method = match &self.method {
+            Method::Read => Method::Read,
+            Method::List(options) => Method::List(options.clone()),
+            Method::ListBuckets => Method::ListBuckets,
+            Method::Head => Method::Head,
+            Method::Delete => Method::Delete,
+            Method::Copy(from) => Method::Copy(from.clone()),
+            Method::Write(Body::Bytes(data)) => Method::Write(Body::Bytes(data.clone())),
+            Method::Write(Body::Empty) => Method::Write(Body::Empty),
+            Method::Write(_) => return None,
+        };
+        Some(Request {
+            method,
+            filename: self.filename.clone(),
+            content_type: self.content_type.clone(),
+            timeout: self.timeout,
+            range: self.range,
+            if_match: self.if_match.clone(),
+            if_none_match: self.if_none_match.clone(),
+        })
+    }
 }
 
 pub struct ResponseFuture {
-    inner: SyncWrapper<Pin<Box<dyn Future<Output = crate::Result<Response>> + Send>>>,
+    inner: SyncWrapper<Pin<Box<dyn Future<Output = Result<Response>> + Send>>>,
 }
 impl Future for ResponseFuture {
-    type Output = crate::Result<Response>;
+    type Output = Result<Response>;
 
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         self.inner.get_mut().as_mut().poll(cx)
     }
 }
 impl ResponseFuture {
-    fn new<F>(value: F) -> Self
+    pub(crate) fn new<F>(value: F) -> Self
     where
-        F: Future<Output = crate::Result<Response>> + Send + 'static,
+        F: Future<Output = Result<Response>> + Send + 'static,
     {
         Self {
             inner: SyncWrapper::new(Box::pin(value)),
@@ -75,52 +160,324 @@ impl fmt::Debug for ResponseFuture {
     }
 }
 
-pub type Filename = String;
-
+/// Async `Client` to make Requests with.
+///
+/// Requests include Method: Read/Write/List, Filename: String.
+///
+/// Default settings with ability to tweak using `Client::builder()`.
+///
+#[derive(Clone)]
 pub struct Client {
+    /// For the requests beyond the `ObjectStore`: multipart, presigning
     pub(crate) inner: S3Client,
+    pub(crate) store: Arc<dyn ObjectStore>,
     pub(crate) config: IOConfig,
 }
 
+/// Settings not set on the builder are read from the environment, then
+/// from the selected profile and the rest of the config file (see the
+/// `settings` module for the schema):
+///
+/// * `S3_BUCKET_NAME` (required)
+/// * `S3_HOST_BASE` endpoint url, e.g. `https://sfo3.digitaloceanspaces.com`
+/// * `S3_REGION_NAME`, then the aws default chain, then `us-east-1`
+/// * credentials from the aws default chain (`AWS_ACCESS_KEY_ID`...)
+/// * `S3_PROFILE` names the profile
+///
 #[must_use]
 pub struct ClientBuilder {
-    config: IOConfig,
+    config: IOConfigBuilder,
+}
+
+impl Default for ClientBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl ClientBuilder {
+    pub fn new() -> Self {
+        ClientBuilder {
+            config: IOConfigBuilder::default(),
+        }
+    }
+
+    pub fn bucket(mut self, bucket_name: impl Into<String>) -> Self {
+        self.config.settings.bucket_name = Some(bucket_name.into());
+        self
+    }
+
+    /// The S3-compatible host, e.g. `https://sfo3.digitaloceanspaces.com`
+    pub fn endpoint_url(mut self, endpoint_url: impl Into<String>) -> Self {
+        self.config.settings.endpoint_url = Some(endpoint_url.into());
+        self
+    }
+
+    /// The host of the bucket, e.g. `https://luci-space.sfo3.digitaloceanspaces.com`
+    /// or s3cmd's `%(bucket)s.sfo3.digitaloceanspaces.com`. Unless
+    /// `force_path_style` is set, a host without the bucket means path-style
+    /// addressing.
+    pub fn host_bucket(mut self, host_bucket: impl Into<String>) -> Self {
+        self.config.settings.host_bucket = Some(host_bucket.into());
+        self
+    }
+
+    pub fn region(mut self, region: impl Into<String>) -> Self {
+        self.config.settings.region = Some(region.into());
+        self
+    }
+
+    /// Static credentials; otherwise the aws default credentials chain is used
+    pub fn credentials(
+        mut self,
+        access_key_id: impl Into<String>,
+        secret_access_key: impl Into<String>,
+        session_token: Option<String>,
+    ) -> Self {
+        self.config.settings.access_key_id = Some(access_key_id.into());
+        self.config.settings.secret_access_key = Some(secret_access_key.into());
+        self.config.settings.session_token = session_token;
+        self
+    }
+
+    pub fn app_name(mut self, app_name: impl Into<String>) -> Self {
+        self.config.settings.app_name = Some(app_name.into());
+        self
+    }
+
+    /// Address the bucket in the path (`host/bucket/key`) rather than the
+    /// host (`bucket.host/key`). Required by most local S3 stand-ins.
+    pub fn force_path_style(mut self, force_path_style: bool) -> Self {
+        self.config.settings.force_path_style = Some(force_path_style);
+        self
+    }
+
+    /// The most requests `get_many` and `put_many` run at once (17 unless
+    /// set here, in `S3_MAX_POOL_CONNECTIONS` or the config file)
+    pub fn max_pool_connections(mut self, max_pool_connections: usize) -> Self {
+        self.config.settings.max_pool_connections = Some(max_pool_connections);
+        self
+    }
+
+    /// How long to wait for a connection to the endpoint
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.config.settings.connect_timeout_ms = Some(timeout.as_millis() as u64);
+        self
+    }
+
+    /// How long any one operation may take, retries included; see
+    /// `Request::with_timeout` for a single request
+    pub fn operation_timeout(mut self, timeout: Duration) -> Self {
+        self.config.settings.operation_timeout_ms = Some(timeout.as_millis() as u64);
+        self
+    }
+
+    /// Use the named profile from the config file (overrides `S3_PROFILE`)
+    pub fn profile(mut self, name: impl Into<String>) -> Self {
+        self.config.profile = Some(name.into());
+        self
+    }
+
+    /// Read this yaml/toml/json file instead of `./config.*` or `S3_CLIENT_CONFIG`
+    pub fn config_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config.config_file = Some(path.into());
+        self
+    }
+
     pub async fn build(self) -> Result<Client> {
-        let config = IOConfig::from_env().await;
+        let config = self.config.build().await?;
         let client = S3Client::from_conf(config.io_cfg.clone());
+        let store = S3Store::new(client.clone(), &config.bucket_name);
 
         Ok(Client {
             inner: client,
+            store: Arc::new(store),
             config,
         })
     }
 }
 
 impl Client {
+    /// Settings default to the environment; see `ClientBuilder`.
+    pub fn builder() -> ClientBuilder {
+        ClientBuilder::new()
+    }
+
+    /// A client of a `LocalStore`, a `MemoryStore`... with the default
+    /// settings. Multipart uploads and presigned urls need S3.
+    pub fn with_store(store: impl ObjectStore + 'static) -> Client {
+        let config = IOConfig::for_bucket(store.bucket());
+        Client {
+            inner: S3Client::from_conf(config.io_cfg.clone()),
+            store: Arc::new(store),
+            config,
+        }
+    }
+
+    /// The bucket all requests are made against
+    pub fn bucket(&self) -> &str {
+        &self.config.bucket_name
+    }
+
+    pub fn list_buckets(&self) -> ResponseFuture {
+        let req = Request::new(Method::ListBuckets, "", None);
+        self.request(req)
+    }
+
+    /// List the first page of objects in the bucket with keys that start with
+    /// `filename`; see `list_stream` for every page
     pub fn list_files(&self, filename: impl AsRef<str>) -> ResponseFuture {
-        let req = Request::new(Method::List(Body::Files), filename, None);
+        let req = Request::new(Method::List(ListOptions::default()), filename, None);
         self.request(req)
     }
 
     pub fn read(&self, filename: impl AsRef<str>, content_type: String) -> ResponseFuture {
-        let req = Request::new(Method::Read(Body::File), filename, Some(content_type));
+        let req = Request::new(Method::Read, filename, Some(content_type));
+        self.request(req)
+    }
+
+    /// Read the whole file into memory
+    pub async fn read_bytes(&self, filename: impl AsRef<str>) -> Result<Bytes> {
+        let key = filename.as_ref();
+        let req = Request::new(Method::Read, key, None);
+        let output = match self.request(req).await?.into_body() {
+            Body::File(output) => output,
+            other => return Err(error::internal(format!("{:?}", other), "Expected a file")),
+        };
+        output
+            .body
+            .collect()
+            .await
+            .map(|data| data.into_bytes())
+            .map_err(|e| error::response(e, "Error reading file").with_key(key))
+    }
+
+    pub fn write(
+        &self,
+        filename: impl AsRef<str>,
+        data: impl Into<Bytes>,
+        content_type: Option<String>,
+    ) -> ResponseFuture {
+        let req = Request::new(
+            Method::Write(Body::Bytes(data.into())),
+            filename,
+            content_type,
+        );
+        self.request(req)
+    }
+
+    /// The file's metadata, without the body
+    pub async fn head(&self, filename: impl AsRef<str>) -> Result<DataFile> {
+        let req = Request::new(Method::Head, filename, None);
+        match self.request(req).await?.into_body() {
+            Body::Head(file) => Ok(file),
+            other => Err(error::internal(format!("{:?}", other), "Expected metadata")),
+        }
+    }
+
+    pub fn delete(&self, filename: impl AsRef<str>) -> ResponseFuture {
+        let req = Request::new(Method::Delete, filename, None);
         self.request(req)
     }
 
-    pub fn write(&self, filename: impl AsRef<str>) -> ResponseFuture {
-        let req = Request::new(Method::Write(Body::Empty), filename, None);
+    /// Copy `from` to `to` within the bucket
+    pub fn copy(&self, from: impl AsRef<str>, to: impl AsRef<str>) -> ResponseFuture {
+        let req = Request::new(Method::Copy(from.as_ref().to_string()), to, None);
         self.request(req)
     }
 
     pub fn request(&self, req: Request) -> ResponseFuture {
-        // engage the S3 request
-        ResponseFuture::new(todo!())
+        let store = self.store.clone();
+        match req.timeout {
+            Some(timeout) => ResponseFuture::new(with_deadline(
+                timeout,
+                req.filename.clone(),
+                execute(store, req),
+            )),
+            None => ResponseFuture::new(execute(store, req)),
+        }
     }
 }
 
+/// `Kind::TimedOut` when `fut` is not done within `timeout`
+async fn with_deadline<F>(timeout: Duration, key: String, fut: F) -> Result<Response>
+where
+    F: Future<Output = Result<Response>>,
+{
+    tokio::time::timeout(timeout, fut)
+        .await
+        .unwrap_or_else(|_| {
+            Err(
+                error::timedout(TimedOut, format!("No response within {:?}", timeout))
+                    .with_key(key),
+            )
+        })
+}
+
+/// Dispatch the request to the matching store operation.
+/// The filename is the object key (or the key prefix when listing).
+async fn execute(store: Arc<dyn ObjectStore>, req: Request) -> Result<Response> {
+    let Request {
+        method,
+        filename: key,
+        content_type,
+        range,
+        if_match,
+        if_none_match,
+        ..
+    } = req;
+
+    let body = match method {
+        Method::ListBuckets => Body::Buckets(store.list_buckets().await?),
+
+        Method::List(options) => Body::Files(store.list(&key, options).await?),
+
+        Method::Read => {
+            if let Some(range) = range.filter(ByteRange::is_empty) {
+                return Err(error::builder(format!("Empty range {:?}", range)).with_key(&key));
+            }
+            let options = GetOptions {
+                range,
+                if_match,
+                content_type,
+            };
+            Body::File(store.get(&key, options).await?)
+        }
+
+        Method::Write(body) => {
+            let data = match body {
+                Body::Bytes(data) => data,
+                Body::Empty => Bytes::new(),
+                other => {
+                    return Err(
+                        error::builder(format!("Cannot write a {:?} body", other)).with_key(&key)
+                    )
+                }
+            };
+            let options = PutOptions {
+                content_type,
+                if_match,
+                if_none_match,
+            };
+            Body::Put(store.put(&key, data, options).await?)
+        }
+
+        Method::Head => Body::Head(store.head(&key).await?),
+
+        Method::Delete => {
+            store.delete(&key).await?;
+            Body::Empty
+        }
+
+        Method::Copy(from) => {
+            store.copy(&from, &key).await?;
+            Body::Empty
+        }
+    };
+
+    Ok(Response::new(body))
+}
+
 impl fmt::Debug for Client {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let mut builder = f.debug_struct("Client");
@@ -164,52 +521,131 @@ impl fmt::Debug for ClientBuilder {
 }
 
 /// Augment the SdkConfig with values that are reused across client requests
+#[derive(Default)]
 struct IOConfigBuilder {
-    etl_obj_filename: Option<String>,
-    app_name: Option<String>,
-    bucket_name: Option<String>,
-    io_cfg: Option<S3Config>,
-    test_project_id: Option<String>,
-}
-
-/// IO for the TNC App
-struct IOConfig {
-    error: Option<crate::error::Error>,
-    etl_obj_filename: String,
-    app_name: String,
-    bucket_name: String,
-    io_cfg: S3Config,
-    test_project_id: Option<String>,
+    /// The builder layer; the last to be applied
+    settings: Settings,
+    config_file: Option<PathBuf>,
+    profile: Option<String>,
 }
 
-impl IOConfig {
-    pub async fn from_env() -> Self {
+impl IOConfigBuilder {
+    /// Layer the config file, the environment and the builder settings
+    async fn build(self) -> Result<IOConfig> {
         dotenv().ok();
         init_tracer();
 
         info!("Loading configuration");
 
-        let bucket_name = std::env::var("S3_BUCKET_NAME").expect("The bucket name must be set");
-        let endpoint_url = std::env::var("S3_HOST_BASE").expect("The host base must be set");
-        let test_project_id = std::env::var("TEST_PROJECT_ID").ok();
-
-        let sdk_config = ::aws_config::load_from_env().await;
-        let sdk_config = aws_sdk_s3::config::Builder::from(&sdk_config)
-            .region(Region::new("us-east-1"))
-            .endpoint_url(endpoint_url)
-            .app_name(AppName::new("TestAndControl".to_string()).unwrap())
-            .build();
+        let config_file = self
+            .config_file
+            .or_else(|| std::env::var_os(CONFIG_FILE_ENV).map(PathBuf::from));
+        let settings = Settings::load(config_file.as_deref(), self.profile.as_deref(), |var| {
+            std::env::var(var).ok()
+        })?
+        .merge(self.settings)
+        .validate()?;
+
+        let app_name = settings.app_name.unwrap_or_else(|| APP_NAME.to_string());
+        let sdk_app_name = AppName::new(app_name.clone())
+            .map_err(|e| error::builder(e).with_msg("Invalid app name"))?;
+
+        let region = RegionProviderChain::first_try(settings.region.map(Region::new))
+            .or_default_provider()
+            .or_else(Region::new("us-east-1"));
+
+        let mut loader = ::aws_config::from_env()
+            .region(region)
+            .app_name(sdk_app_name);
+        if let Some(endpoint_url) = settings.endpoint_url {
+            loader = loader.endpoint_url(endpoint_url);
+        }
+        if let (Some(access_key_id), Some(secret_access_key)) =
+            (settings.access_key_id, settings.secret_access_key)
+        {
+            loader = loader.credentials_provider(Credentials::new(
+                access_key_id,
+                secret_access_key,
+                settings.session_token,
+                None,
+                "s3-client",
+            ));
+        }
+        let mut timeout_config = TimeoutConfig::builder();
+        timeout_config
+            .set_connect_timeout(settings.connect_timeout_ms.map(Duration::from_millis))
+            .set_operation_timeout(settings.operation_timeout_ms.map(Duration::from_millis));
+        let sdk_config = loader.timeout_config(timeout_config.build()).load().await;
+
+        let mut s3_config = aws_sdk_s3::config::Builder::from(&sdk_config);
+        let force_path_style = settings.force_path_style.or_else(|| {
+            let host_bucket = settings.host_bucket.as_deref()?;
+            let bucket = settings.bucket_name.as_deref()?;
+            Some(!is_virtual_host(host_bucket, bucket))
+        });
+        if let Some(force_path_style) = force_path_style {
+            s3_config = s3_config.force_path_style(force_path_style);
+        }
+        let sdk_config = s3_config.build();
 
         debug!("Sdk Config: {:?}", sdk_config);
+        Ok(IOConfig {
+            etl_obj_filename: settings
+                .etl_obj_filename
+                .unwrap_or_else(|| ETL_OBJ_FILENAME.to_string()),
+            app_name,
+            // validated
+            bucket_name: settings.bucket_name.unwrap_or_default(),
+            host_bucket: settings.host_bucket,
+            max_pool_connections: settings
+                .max_pool_connections
+                .unwrap_or(MAX_POOL_CONNECTIONS),
+            io_cfg: sdk_config,
+            test_project_id: settings.test_project_id,
+        })
+    }
+}
+
+/// IO for the TNC App
+#[allow(dead_code)]
+#[derive(Clone)]
+pub(crate) struct IOConfig {
+    pub(crate) etl_obj_filename: String,
+    pub(crate) app_name: String,
+    pub(crate) bucket_name: String,
+    pub(crate) host_bucket: Option<String>,
+    pub(crate) max_pool_connections: usize,
+    pub(crate) io_cfg: S3Config,
+    pub(crate) test_project_id: Option<String>,
+}
+
+impl IOConfig {
+    /// The defaults, for a client of another store
+    fn for_bucket(bucket_name: &str) -> Self {
         IOConfig {
-            error: None,
             etl_obj_filename: ETL_OBJ_FILENAME.to_string(),
             app_name: APP_NAME.to_string(),
-            bucket_name,
-            io_cfg: sdk_config,
-            test_project_id,
+            bucket_name: bucket_name.to_string(),
+            host_bucket: None,
+            max_pool_connections: MAX_POOL_CONNECTIONS,
+            io_cfg: S3Config::builder().region(Region::new("us-east-1")).build(),
+            test_project_id: None,
         }
     }
+
+    #[allow(dead_code)]
+    pub async fn from_env() -> Result<Self> {
+        IOConfigBuilder::default().build().await
+    }
+}
+
+/// Whether `host_bucket` names the bucket in the host (virtual-host
+/// addressing), as a url or as an s3cmd template
+fn is_virtual_host(host_bucket: &str, bucket: &str) -> bool {
+    let host = host_bucket
+        .split_once("://")
+        .map_or(host_bucket, |(_, rest)| rest);
+    host.starts_with("%(bucket)s.") || host.starts_with(&format!("{}.", bucket))
 }
 
 fn init_tracer() {
@@ -218,5 +654,101 @@ fn init_tracer() {
     #[cfg(not(debug_assertions))]
     let tracer = tracing_subscriber::fmt().json();
 
-    tracer.with_env_filter(EnvFilter::from_default_env()).init();
+    // a subscriber may already be set by the app or an earlier client
+    let _ = tracer
+        .with_env_filter(EnvFilter::from_default_env())
+        .try_init();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn builder_overrides_env() {
+        let client = Client::builder()
+            .bucket("test-bucket")
+            .endpoint_url("http://127.0.0.1:9000")
+            .region("sfo3")
+            .credentials("id", "secret", None)
+            .force_path_style(true)
+            .build()
+            .await
+            .unwrap();
+
+        assert_eq!(client.bucket(), "test-bucket");
+        assert_eq!(client.config.io_cfg.region(), Some(&Region::new("sfo3")));
+    }
+
+    #[test]
+    fn addressing_from_host_bucket() {
+        assert!(is_virtual_host(
+            "https://luci-space.sfo3.digitaloceanspaces.com",
+            "luci-space"
+        ));
+        assert!(is_virtual_host(
+            "%(bucket)s.sfo3.digitaloceanspaces.com",
+            "luci-space"
+        ));
+        assert!(!is_virtual_host(
+            "sfo3.digitaloceanspaces.com",
+            "luci-space"
+        ));
+        assert!(!is_virtual_host("http://localhost:9000", "luci-space"));
+    }
+
+    #[tokio::test]
+    async fn builder_invalid_app_name() {
+        let err = Client::builder()
+            .bucket("test-bucket")
+            .app_name("not a valid app name!")
+            .build()
+            .await
+            .unwrap_err();
+
+        assert!(err.is_builder());
+    }
+
+    #[tokio::test]
+    async fn builder_missing_config_file() {
+        let err = Client::builder()
+            .bucket("test-bucket")
+            .config_file("does-not-exist.yaml")
+            .build()
+            .await
+            .unwrap_err();
+
+        assert!(err.is_builder());
+    }
+
+    #[tokio::test]
+    async fn request_deadline() {
+        // accepts connections (in the backlog) but never answers
+        let silent = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = Client::builder()
+            .bucket("test-bucket")
+            .endpoint_url(format!("http://{}", silent.local_addr().unwrap()))
+            .credentials("id", "secret", None)
+            .force_path_style(true)
+            .connect_timeout(Duration::from_secs(1))
+            .build()
+            .await
+            .unwrap();
+
+        let req =
+            Request::new(Method::Read, "etlObj.json", None).with_timeout(Duration::from_millis(50));
+        let err = client.request(req).await.unwrap_err();
+
+        assert!(err.is_timedout(), "{:?}", err);
+        assert_eq!(err.key(), Some("etlObj.json"));
+        assert_eq!(
+            client
+                .config
+                .io_cfg
+                .timeout_config()
+                .unwrap()
+                .connect_timeout(),
+            Some(Duration::from_secs(1))
+        );
+    }
 }
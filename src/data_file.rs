@@ -0,0 +1,165 @@
+use aws_sdk_s3::operation::list_objects_v2::ListObjectsV2Output;
+use aws_sdk_s3::primitives::DateTime;
+use aws_sdk_s3::types::Object;
+use serde::{Deserialize, Serialize};
+
+/// A row of a file table; serializes with camelCase keys and an RFC 3339
+/// `lastModified`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DataFile {
+    pub bucket: String,
+    pub key: String,
+    /// The last segment of the key
+    pub display_name: String,
+    pub size: i64,
+    /// May be missing, e.g. for a `DataFile::new`
+    #[serde(default, with = "rfc3339")]
+    pub last_modified: Option<DateTime>,
+    /// As returned by S3, quotes included
+    pub etag: Option<String>,
+    pub storage_class: Option<String>,
+}
+
+impl DataFile {
+    /// A file with only the size known
+    pub fn new(bucket: impl Into<String>, key: impl Into<String>, size: i64) -> DataFile {
+        let key = key.into();
+        DataFile {
+            bucket: bucket.into(),
+            display_name: display_name(&key).to_string(),
+            key,
+            size,
+            last_modified: None,
+            etag: None,
+            storage_class: None,
+        }
+    }
+
+    pub fn from_object(bucket: impl Into<String>, object: &Object) -> DataFile {
+        let key = object.key().unwrap_or_default().to_string();
+        DataFile {
+            bucket: bucket.into(),
+            display_name: display_name(&key).to_string(),
+            key,
+            size: object.size(),
+            last_modified: object.last_modified().cloned(),
+            etag: object.e_tag().map(str::to_string),
+            storage_class: object
+                .storage_class()
+                .map(|class| class.as_str().to_string()),
+        }
+    }
+
+    /// The files of one page of a listing
+    pub fn from_listing(listing: &ListObjectsV2Output) -> Vec<DataFile> {
+        let bucket = listing.name().unwrap_or_default();
+        listing
+            .contents()
+            .unwrap_or_default()
+            .iter()
+            .map(|object| DataFile::from_object(bucket, object))
+            .collect()
+    }
+}
+
+/// Utility that extracts filename from object key
+fn display_name(key: &str) -> &str {
+    let key = key.trim_end_matches('/');
+    key.rsplit_once('/')
+        .map(|(_, filename)| filename)
+        .unwrap_or(key)
+}
+
+mod rfc3339 {
+    use aws_sdk_s3::primitives::{DateTime, DateTimeFormat};
+    use serde::{de, ser, Deserialize, Deserializer, Serializer};
+
+    pub(super) fn serialize<S: Serializer>(
+        value: &Option<DateTime>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match value {
+            Some(value) => {
+                let value = value
+                    .fmt(DateTimeFormat::DateTime)
+                    .map_err(ser::Error::custom)?;
+                serializer.serialize_some(&value)
+            }
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<DateTime>, D::Error> {
+        Option::<String>::deserialize(deserializer)?
+            .map(|value| DateTime::from_str(&value, DateTimeFormat::DateTime))
+            .transpose()
+            .map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aws_sdk_s3::types::ObjectStorageClass;
+
+    #[test]
+    fn from_listing() {
+        let listing = ListObjectsV2Output::builder()
+            .name("luci-space")
+            .contents(
+                Object::builder()
+                    .key("p/shared/datafiles/target_list.csv")
+                    .size(52418)
+                    .e_tag("\"abc\"")
+                    .storage_class(ObjectStorageClass::Standard)
+                    .last_modified(DateTime::from_secs(1_700_000_000))
+                    .build(),
+            )
+            .build();
+
+        let files = DataFile::from_listing(&listing);
+
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].bucket, "luci-space");
+        assert_eq!(files[0].display_name, "target_list.csv");
+        assert_eq!(files[0].storage_class.as_deref(), Some("STANDARD"));
+    }
+
+    #[test]
+    fn serde_roundtrip() {
+        let file = DataFile {
+            bucket: "luci-space".to_string(),
+            key: "p/etlObj.json".to_string(),
+            display_name: "etlObj.json".to_string(),
+            size: 10,
+            last_modified: Some(DateTime::from_secs(1_700_000_000)),
+            etag: None,
+            storage_class: None,
+        };
+
+        let json = serde_json::to_value(&file).unwrap();
+
+        assert_eq!(json["lastModified"], "2023-11-14T22:13:20Z");
+        assert_eq!(json["displayName"], "etlObj.json");
+        assert_eq!(serde_json::from_value::<DataFile>(json).unwrap(), file);
+    }
+
+    #[test]
+    fn last_modified_may_be_missing() {
+        let json = serde_json::json!({
+            "bucket": "luci-space",
+            "key": "p/etlObj.json",
+            "displayName": "etlObj.json",
+            "size": 10,
+            "etag": null,
+            "storageClass": null
+        });
+
+        let file = serde_json::from_value::<DataFile>(json).unwrap();
+
+        assert_eq!(file, DataFile::new("luci-space", "p/etlObj.json", 10));
+    }
+}
@@ -0,0 +1,385 @@
+//! Downloads that do not hold the whole file in memory: `read_stream`
+//! yields the body chunk by chunk (also as an `AsyncRead`), `read_to_file`
+//! writes it to disk as it arrives. A `ResumableDownload` picks up where an
+//! interrupted one stopped, with `Range` and `If-Match` so that the parts
+//! come from the same version of the object.
+//!
+use std::fmt;
+use std::io;
+use std::ops::{Range, RangeFrom};
+use std::path::Path;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+
+use aws_sdk_s3::primitives::ByteStream;
+use bytes::Bytes;
+use futures::stream::{Stream, TryStreamExt};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt, ReadBuf};
+use tracing::warn;
+
+use crate::client::{Client, Request};
+use crate::error::{self, Error, Result};
+use crate::response::{Body, Method};
+
+/// The bytes `start..end` of an object; no `end` reads to the end.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteRange {
+    start: u64,
+    end: Option<u64>,
+}
+
+impl ByteRange {
+    pub fn new(start: u64, end: Option<u64>) -> Self {
+        ByteRange { start, end }
+    }
+    pub fn start(&self) -> u64 {
+        self.start
+    }
+    /// Exclusive
+    pub fn end(&self) -> Option<u64> {
+        self.end
+    }
+    pub fn is_empty(&self) -> bool {
+        matches!(self.end, Some(end) if end <= self.start)
+    }
+}
+
+impl From<Range<u64>> for ByteRange {
+    fn from(range: Range<u64>) -> Self {
+        ByteRange::new(range.start, Some(range.end))
+    }
+}
+
+impl From<RangeFrom<u64>> for ByteRange {
+    fn from(range: RangeFrom<u64>) -> Self {
+        ByteRange::new(range.start, None)
+    }
+}
+
+/// The `Range` header value, e.g. `bytes=0-99` (inclusive)
+impl fmt::Display for ByteRange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.end {
+            Some(end) => write!(f, "bytes={}-{}", self.start, end.saturating_sub(1)),
+            None => write!(f, "bytes={}-", self.start),
+        }
+    }
+}
+
+/// The body of an object as it arrives; a `Stream` of chunks and an
+/// `AsyncRead`.
+pub struct ReadStream {
+    key: String,
+    body: ByteStream,
+    content_length: i64,
+    e_tag: Option<String>,
+    /// What `poll_read` has not handed out yet
+    chunk: Bytes,
+}
+
+impl ReadStream {
+    /// The object key
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+    /// The number of bytes in the body (the range, when there is one)
+    pub fn content_length(&self) -> i64 {
+        self.content_length
+    }
+    /// As returned by S3, quotes included
+    pub fn e_tag(&self) -> Option<&str> {
+        self.e_tag.as_deref()
+    }
+}
+
+impl Stream for ReadStream {
+    type Item = Result<Bytes>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        if !this.chunk.is_empty() {
+            return Poll::Ready(Some(Ok(std::mem::take(&mut this.chunk))));
+        }
+        Pin::new(&mut this.body)
+            .poll_next(cx)
+            .map_err(|e| error::dispatch(e, "Error reading file").with_key(&this.key))
+    }
+}
+
+impl AsyncRead for ReadStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        while self.chunk.is_empty() {
+            let this = &mut *self;
+            match ready!(Pin::new(&mut this.body).poll_next(cx)) {
+                Some(Ok(chunk)) => this.chunk = chunk,
+                Some(Err(e)) => {
+                    let err = error::dispatch(e, "Error reading file").with_key(&this.key);
+                    return Poll::Ready(Err(err.into_io()));
+                }
+                None => return Poll::Ready(Ok(())),
+            }
+        }
+        let n = self.chunk.len().min(buf.remaining());
+        buf.put_slice(&self.chunk.split_to(n));
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl fmt::Debug for ReadStream {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ReadStream")
+            .field("key", &self.key)
+            .field("content_length", &self.content_length)
+            .field("e_tag", &self.e_tag)
+            .finish()
+    }
+}
+
+impl Client {
+    /// The body of the file, as it arrives
+    pub async fn read_stream(&self, key: impl AsRef<str>) -> Result<ReadStream> {
+        self.stream(Request::new(Method::Read, key, None)).await
+    }
+
+    /// Part of the file, e.g. `0..1024` or `1024..`
+    pub async fn read_stream_range(
+        &self,
+        key: impl AsRef<str>,
+        range: impl Into<ByteRange>,
+    ) -> Result<ReadStream> {
+        self.stream(Request::new(Method::Read, key, None).with_range(range))
+            .await
+    }
+
+    /// The bytes `start..end` of the file, e.g. the header of a csv
+    pub async fn read_range(&self, key: impl AsRef<str>, range: Range<u64>) -> Result<Bytes> {
+        let chunks: Vec<Bytes> = self
+            .read_stream_range(key, range)
+            .await?
+            .try_collect()
+            .await?;
+        Ok(chunks.concat().into())
+    }
+
+    /// Write the file to `path` chunk by chunk, resuming after network
+    /// failures; returns the number of bytes written. `path` is created or
+    /// truncated.
+    pub async fn read_to_file(&self, key: impl AsRef<str>, path: impl AsRef<Path>) -> Result<u64> {
+        let path = path.as_ref();
+        let mut file = tokio::fs::File::create(path)
+            .await
+            .map_err(|e| error::internal(e, format!("Error writing {}", path.display())))?;
+        self.resumable_download(key).write_to(&mut file).await
+    }
+
+    /// A download that can continue after an interruption
+    pub fn resumable_download(&self, key: impl AsRef<str>) -> ResumableDownload {
+        ResumableDownload {
+            client: self.clone(),
+            key: key.as_ref().to_string(),
+            e_tag: None,
+            received: 0,
+            total: None,
+            max_resumes: MAX_RESUMES,
+        }
+    }
+
+    async fn stream(&self, req: Request) -> Result<ReadStream> {
+        let key = req.filename().to_string();
+        let output = match self.request(req).await?.into_body() {
+            Body::File(output) => output,
+            other => return Err(error::internal(format!("{:?}", other), "Expected a file")),
+        };
+        Ok(ReadStream {
+            content_length: output.content_length(),
+            e_tag: output.e_tag,
+            body: output.body,
+            key,
+            chunk: Bytes::new(),
+        })
+    }
+}
+
+const MAX_RESUMES: u32 = 3;
+
+/// What has been received of a download: the ETag of the object and the
+/// number of bytes. `write_to` again after a failure sends the rest.
+#[derive(Debug)]
+pub struct ResumableDownload {
+    client: Client,
+    key: String,
+    e_tag: Option<String>,
+    received: u64,
+    /// The size of the object, once known
+    total: Option<u64>,
+    max_resumes: u32,
+}
+
+impl ResumableDownload {
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+    /// The version being downloaded, once the first response is in
+    pub fn e_tag(&self) -> Option<&str> {
+        self.e_tag.as_deref()
+    }
+    pub fn received(&self) -> u64 {
+        self.received
+    }
+    pub fn is_done(&self) -> bool {
+        matches!(self.total, Some(total) if self.received >= total)
+    }
+    /// How many times `write_to` continues by itself after a network
+    /// failure (3 unless set)
+    pub fn max_resumes(mut self, max_resumes: u32) -> Self {
+        self.max_resumes = max_resumes;
+        self
+    }
+
+    /// Write what has not been received yet to `writer`; returns the number
+    /// of bytes received in all. A `Kind::Conflict` error means the object
+    /// changed since the download started.
+    pub async fn write_to<W>(&mut self, writer: &mut W) -> Result<u64>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        let mut resumes = 0;
+        loop {
+            match self.write_rest(writer).await {
+                Ok(()) => return Ok(self.received),
+                Err(Interrupted::Read(e)) if e.is_transient() && resumes < self.max_resumes => {
+                    resumes += 1;
+                    warn!(
+                        "Resuming {} at byte {} after: {}",
+                        self.key, self.received, e
+                    );
+                }
+                Err(Interrupted::Read(e) | Interrupted::Write(e)) => return Err(e),
+            }
+        }
+    }
+
+    async fn write_rest<W>(&mut self, writer: &mut W) -> std::result::Result<(), Interrupted>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        if self.is_done() {
+            return Ok(());
+        }
+        let mut req = Request::new(Method::Read, &self.key, None);
+        if let Some(range) = self.next_range() {
+            req = req.with_range(range);
+        }
+        if let Some(e_tag) = &self.e_tag {
+            req = req.with_if_match(e_tag);
+        }
+
+        let mut stream = self.client.stream(req).await.map_err(Interrupted::Read)?;
+        if self.e_tag.is_none() {
+            self.e_tag = stream.e_tag.clone();
+        }
+        self.total = Some(self.received + stream.content_length.max(0) as u64);
+
+        while let Some(chunk) = stream.try_next().await.map_err(Interrupted::Read)? {
+            writer.write_all(&chunk).await.map_err(|e| {
+                Interrupted::Write(
+                    error::internal(e, "Error writing the download").with_key(&self.key),
+                )
+            })?;
+            self.received += chunk.len() as u64;
+        }
+        writer.flush().await.map_err(|e| {
+            Interrupted::Write(error::internal(e, "Error writing the download").with_key(&self.key))
+        })
+    }
+
+    /// Where the next request starts, if not at the beginning
+    fn next_range(&self) -> Option<ByteRange> {
+        (self.received > 0).then(|| ByteRange::from(self.received..))
+    }
+}
+
+/// Only failures to read from S3 are resumed
+enum Interrupted {
+    Read(Error),
+    Write(Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+
+    fn stream(data: &'static [u8]) -> ReadStream {
+        ReadStream {
+            key: "p/shared/datafiles/big.csv".to_string(),
+            body: ByteStream::from_static(data),
+            content_length: data.len() as i64,
+            e_tag: None,
+            chunk: Bytes::new(),
+        }
+    }
+
+    #[test]
+    fn range_header() {
+        assert_eq!(ByteRange::from(0..100).to_string(), "bytes=0-99");
+        assert_eq!(ByteRange::from(100..).to_string(), "bytes=100-");
+        assert!(ByteRange::from(5..5).is_empty());
+    }
+
+    #[tokio::test]
+    async fn reads_in_small_buffers() {
+        let mut reader = stream(b"a,b\n1,2\n");
+        let mut buf = [0u8; 3];
+        let mut data = Vec::new();
+        loop {
+            let n = reader.read(&mut buf).await.unwrap();
+            if n == 0 {
+                break;
+            }
+            data.extend_from_slice(&buf[..n]);
+        }
+        assert_eq!(data, b"a,b\n1,2\n");
+    }
+
+    #[tokio::test]
+    async fn resumes_after_received_bytes() {
+        let client = Client::with_store(crate::MemoryStore::new("test-bucket"));
+        let key = "p/shared/datafiles/big.csv";
+        client.write(key, "a,b\n1,2\n3,4\n", None).await.unwrap();
+        let mut download = client.resumable_download(key);
+        assert_eq!(download.next_range(), None);
+        let mut first = Vec::new();
+        download.write_to(&mut first).await.unwrap();
+        let e_tag = download.e_tag().unwrap().to_string();
+
+        // as if the first 4 bytes had been received before an interruption
+        let mut download = client.resumable_download(key);
+        download.e_tag = Some(e_tag.clone());
+        download.received = 4;
+        assert_eq!(download.next_range(), Some(ByteRange::from(4..)));
+        let mut rest = Vec::new();
+        assert_eq!(download.write_to(&mut rest).await.unwrap(), 12);
+        assert_eq!(rest, b"1,2\n3,4\n");
+        assert!(download.is_done());
+        assert_eq!([&first[..4], &rest[..]].concat(), first);
+
+        // the object changed in between
+        client.write(key, "a,b\n5,6\n", None).await.unwrap();
+        let mut download = client.resumable_download(key);
+        download.e_tag = Some(e_tag);
+        download.received = 4;
+        let err = download.write_to(&mut Vec::new()).await.unwrap_err();
+        assert!(err.is_conflict(), "{:?}", err);
+    }
+
+    #[tokio::test]
+    async fn chunks() {
+        let chunks: Vec<Bytes> = stream(b"a,b\n").try_collect().await.unwrap();
+        assert_eq!(chunks.concat(), b"a,b\n");
+    }
+}
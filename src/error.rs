@@ -3,6 +3,8 @@ use std::fmt;
 use std::io;
 use url::Url;
 
+use aws_sdk_s3::error::{ProvideErrorMetadata, SdkError};
+
 // use std::convert::From;
 // use thiserror::Error;
 //
@@ -20,7 +22,42 @@ pub type Result<T> = std::result::Result<T, Error>;
 pub fn into(err: impl Into<BoxError>, kind: Kind) -> Error {
     Error::new(kind, Some(err))
 }
-///
+
+/// sdk error -> this package error, with the Kind read from the failure:
+/// timeouts, then the S3 error code, then the HTTP status.
+pub fn from_sdk<E>(err: SdkError<E>) -> Error
+where
+    E: ProvideErrorMetadata + StdError + Send + Sync + 'static,
+{
+    let status = err.raw_response().map(|raw| raw.status().as_u16());
+    let code = err.code().map(str::to_string);
+    let kind = match &err {
+        SdkError::TimeoutError(_) => Kind::TimedOut,
+        SdkError::DispatchFailure(failure) if failure.is_timeout() => Kind::TimedOut,
+        SdkError::DispatchFailure(_) => Kind::Request,
+        SdkError::ConstructionFailure(_) => Kind::Builder,
+        SdkError::ResponseError(_) => Kind::Response,
+        SdkError::ServiceError(_) => Kind::from_service(code.as_deref(), status),
+        _ => Kind::Request,
+    };
+
+    let dispatch = matches!(err, SdkError::DispatchFailure(_));
+    let mut error = Error::new(kind, Some(err));
+    error.inner.dispatch = dispatch;
+    error.inner.status = status;
+    error.inner.code = code;
+    error
+}
+
+impl<E> From<SdkError<E>> for Error
+where
+    E: ProvideErrorMetadata + StdError + Send + Sync + 'static,
+{
+    fn from(err: SdkError<E>) -> Error {
+        from_sdk(err)
+    }
+}
+
 pub struct Error {
     inner: Box<Inner>,
 }
@@ -33,6 +70,12 @@ struct Inner {
     url: Option<Url>,
     key: Option<String>,
     msg: Option<String>,
+    /// HTTP status of the S3 response
+    status: Option<u16>,
+    /// S3 error code, e.g. NoSuchKey
+    code: Option<String>,
+    /// The connection failed on the way to S3 or back
+    dispatch: bool,
 }
 
 impl Error {
@@ -47,6 +90,9 @@ impl Error {
                 url: None,
                 key: None,
                 msg: None,
+                status: None,
+                code: None,
+                dispatch: false,
             }),
         }
     }
@@ -77,6 +123,20 @@ impl Error {
         self.inner.url = None;
         self
     }
+    /// Returns the HTTP status of the S3 response, if there was one.
+    pub fn status(&self) -> Option<u16> {
+        self.inner.status
+    }
+
+    /// Returns the S3 error code (e.g. `NoSuchKey`), if there was one.
+    pub fn code(&self) -> Option<&str> {
+        self.inner.code.as_deref()
+    }
+
+    /// Returns the S3 key related to this error.
+    pub fn key(&self) -> Option<&str> {
+        self.inner.key.as_deref()
+    }
     /// Add a S3 key
     #[allow(dead_code)]
     pub fn with_key(mut self, key: impl AsRef<str>) -> Self {
@@ -124,14 +184,79 @@ impl Error {
     pub fn is_unauthorized(&self) -> bool {
         matches!(self.inner.kind, Kind::Unauthorized)
     }
+    /// Returns true if the error, or any error in its source chain (through
+    /// `io::Error` too), is a timeout.
     pub fn is_timedout(&self) -> bool {
-        matches!(self.inner.kind, Kind::TimedOut)
+        if matches!(self.inner.kind, Kind::TimedOut) {
+            return true;
+        }
+        let mut source = self.source();
+        while let Some(err) = source {
+            if err.is::<TimedOut>() {
+                return true;
+            }
+            if let Some(err) = err.downcast_ref::<Error>() {
+                if matches!(err.inner.kind, Kind::TimedOut) {
+                    return true;
+                }
+            }
+            if let Some(io) = err.downcast_ref::<io::Error>() {
+                if io.kind() == io::ErrorKind::TimedOut {
+                    return true;
+                }
+                // io::Error::source skips the wrapped error itself
+                if let Some(inner) = io.get_ref() {
+                    if inner.is::<TimedOut>() {
+                        return true;
+                    }
+                    if let Some(err) = inner.downcast_ref::<Error>() {
+                        if err.is_timedout() {
+                            return true;
+                        }
+                    }
+                }
+            }
+            source = err.source();
+        }
+        false
+    }
+    pub fn is_malformed_data(&self) -> bool {
+        matches!(self.inner.kind, Kind::MalformedData)
+    }
+    pub fn is_not_found(&self) -> bool {
+        matches!(self.inner.kind, Kind::NotFound)
+    }
+    pub fn is_conflict(&self) -> bool {
+        matches!(self.inner.kind, Kind::Conflict)
+    }
+    pub fn is_throttled(&self) -> bool {
+        matches!(self.inner.kind, Kind::Throttled)
+    }
+    /// The request could not be sent, or the response was cut off, by a
+    /// network failure
+    pub fn is_dispatch(&self) -> bool {
+        self.inner.dispatch
+    }
+    /// Throttling, timeouts, 5xx responses and failed connections: the same
+    /// request may well succeed later. Other `Kind::Request` errors (e.g. a
+    /// local file that cannot be read) are not.
+    pub fn is_transient(&self) -> bool {
+        match self.inner.kind {
+            Kind::Throttled | Kind::TimedOut => true,
+            Kind::Request if self.inner.dispatch => true,
+            Kind::Response => matches!(self.inner.status, Some(500..=599)),
+            _ => self.is_timedout(),
+        }
+    }
+    /// The kind of error
+    pub fn kind(&self) -> &Kind {
+        &self.inner.kind
     }
 
     // private
     #[allow(unused)]
     pub(crate) fn into_io(self) -> io::Error {
-        io::Error::new(io::ErrorKind::Other, self)
+        io::Error::other(self)
     }
 }
 
@@ -150,6 +275,12 @@ impl fmt::Debug for Error {
         if let Some(ref msg) = self.inner.msg {
             builder.field("msg", msg);
         }
+        if let Some(ref status) = self.inner.status {
+            builder.field("status", status);
+        }
+        if let Some(ref code) = self.inner.code {
+            builder.field("code", code);
+        }
         if let Some(ref source) = self.inner.source {
             builder.field("source", source);
         }
@@ -170,8 +301,15 @@ impl fmt::Display for Error {
             Kind::Unauthorized => f.write_str("unauthorized")?,
             Kind::TimedOut => f.write_str("timed-out")?,
             Kind::MalformedData => f.write_str("malformed data")?,
+            Kind::NotFound => f.write_str("not found")?,
+            Kind::Conflict => f.write_str("conflict")?,
+            Kind::Throttled => f.write_str("throttled")?,
         };
 
+        if let Some(code) = &self.inner.code {
+            write!(f, " ({})", code)?;
+        }
+
         if let Some(url) = &self.inner.url {
             write!(f, " for url ({})", url.as_str())?;
         }
@@ -194,7 +332,7 @@ impl StdError for Error {
 }
 
 #[allow(dead_code)]
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Kind {
     Decode,
     Builder,
@@ -205,6 +343,36 @@ pub enum Kind {
     TimedOut,
     MissingParameter,
     MalformedData,
+    /// No such key, bucket or upload
+    NotFound,
+    /// A precondition (If-Match...) failed or the request raced another
+    Conflict,
+    /// S3 asked to slow down
+    Throttled,
+}
+
+impl Kind {
+    /// The S3 error code when it is one we know, else the HTTP status
+    fn from_service(code: Option<&str>, status: Option<u16>) -> Kind {
+        match code {
+            Some("NoSuchKey" | "NoSuchBucket" | "NoSuchUpload" | "NotFound") => Kind::NotFound,
+            Some(
+                "AccessDenied" | "InvalidAccessKeyId" | "SignatureDoesNotMatch" | "ExpiredToken",
+            ) => Kind::Unauthorized,
+            Some("PreconditionFailed" | "ConditionalRequestConflict") => Kind::Conflict,
+            Some("SlowDown" | "Throttling" | "TooManyRequests" | "RequestLimitExceeded") => {
+                Kind::Throttled
+            }
+            Some("RequestTimeout") => Kind::TimedOut,
+            _ => match status {
+                Some(401 | 403) => Kind::Unauthorized,
+                Some(404) => Kind::NotFound,
+                Some(409 | 412) => Kind::Conflict,
+                Some(429 | 503) => Kind::Throttled,
+                _ => Kind::Response,
+            },
+        }
+    }
 }
 
 // constructors
@@ -224,6 +392,12 @@ pub(crate) fn internal<E: Into<BoxError>>(e: E, msg: impl AsRef<str>) -> Error {
 pub(crate) fn request<E: Into<BoxError>>(e: E, msg: impl AsRef<str>) -> Error {
     Error::new(Kind::Request, Some(e)).with_msg(msg)
 }
+/// A `Kind::Request` error from the network, see `Error::is_dispatch`
+pub(crate) fn dispatch<E: Into<BoxError>>(e: E, msg: impl AsRef<str>) -> Error {
+    let mut error = request(e, msg);
+    error.inner.dispatch = true;
+    error
+}
 #[allow(unused)]
 pub(crate) fn response<E: Into<BoxError>>(e: E, msg: impl AsRef<str>) -> Error {
     Error::new(Kind::Response, Some(e)).with_msg(msg)
@@ -234,7 +408,6 @@ pub(crate) fn unauthorized<E: Into<BoxError>>(e: E, url: Url, msg: impl AsRef<st
         .with_url(url)
         .with_msg(msg)
 }
-#[allow(unused)]
 pub(crate) fn timedout<E: Into<BoxError>>(e: E, msg: impl AsRef<str>) -> Error {
     Error::new(Kind::TimedOut, Some(e)).with_msg(msg)
 }
@@ -243,6 +416,13 @@ pub(crate) fn missing_parameter<E: Into<BoxError>>(e: E, msg: impl AsRef<str>) -
     Error::new(Kind::MissingParameter, Some(e)).with_msg(msg)
 }
 
+pub(crate) fn not_found<E: Into<BoxError>>(e: E, msg: impl AsRef<str>) -> Error {
+    Error::new(Kind::NotFound, Some(e)).with_msg(msg)
+}
+pub(crate) fn conflict<E: Into<BoxError>>(e: E, msg: impl AsRef<str>) -> Error {
+    Error::new(Kind::Conflict, Some(e)).with_msg(msg)
+}
+
 /// constructor for MalformedData error that includes a message
 #[allow(unused)]
 pub(crate) fn malformed_data<E: Into<BoxError>>(e: E, msg: impl AsRef<str>) -> Error {
@@ -320,8 +500,68 @@ mod tests {
         let err = super::request(super::TimedOut, "test message");
         assert!(err.is_timedout());
 
-        let io = io::Error::new(io::ErrorKind::Other, err);
+        let io = io::Error::other(err);
         let nested = super::request(io, "test message");
         assert!(nested.is_timedout());
     }
+
+    #[test]
+    fn timedout_io_error() {
+        let io = io::Error::new(io::ErrorKind::TimedOut, "connect");
+        assert!(super::request(io, "test message").is_timedout());
+        assert!(!super::request("refused", "test message").is_timedout());
+    }
+
+    fn service_error(code: &str, status: u16) -> Error {
+        use aws_sdk_s3::operation::get_object::GetObjectError;
+        use aws_sdk_s3::primitives::SdkBody;
+        use aws_smithy_types::error::ErrorMetadata;
+
+        let err = GetObjectError::generic(ErrorMetadata::builder().code(code).build());
+        let raw = http::Response::builder()
+            .status(status)
+            .body(SdkBody::empty())
+            .unwrap();
+        SdkError::service_error(err, raw).into()
+    }
+
+    #[test]
+    fn sdk_error_kinds() {
+        let err = service_error("NoSuchKey", 404);
+        assert!(err.is_not_found());
+        assert_eq!(err.status(), Some(404));
+        assert_eq!(err.code(), Some("NoSuchKey"));
+
+        assert!(service_error("NoSuchBucket", 404).is_not_found());
+        assert!(service_error("AccessDenied", 403).is_unauthorized());
+        assert!(service_error("SlowDown", 503).is_throttled());
+        assert!(service_error("PreconditionFailed", 412).is_conflict());
+        assert!(service_error("InternalError", 500).is_response());
+    }
+
+    #[test]
+    fn transient() {
+        assert!(super::dispatch("reset", "Error reading file").is_transient());
+        assert!(!super::request("refused", "Error reading part 1").is_transient());
+        assert!(super::request(super::TimedOut, "test message").is_transient());
+        assert!(service_error("InternalError", 500).is_transient());
+        assert!(!service_error("NoSuchKey", 404).is_transient());
+    }
+
+    #[test]
+    fn sdk_error_status_without_code() {
+        assert!(service_error("", 403).is_unauthorized());
+        assert!(service_error("", 404).is_not_found());
+        assert!(service_error("", 412).is_conflict());
+        assert!(service_error("", 503).is_throttled());
+    }
+
+    #[test]
+    fn sdk_timeout() {
+        use aws_sdk_s3::operation::get_object::GetObjectError;
+
+        let err: Error = SdkError::<GetObjectError>::timeout_error("too slow").into();
+        assert!(err.is_timedout());
+        assert_eq!(err.status(), None);
+    }
 }
@@ -1,3 +1,5 @@
+use schemars::gen::SchemaSettings;
+use schemars::JsonSchema;
 use serde::de::{self, Visitor};
 use serde::{Deserialize, Deserializer, Serialize};
 use serde_json;
@@ -30,12 +32,37 @@ impl fmt::Display for EtlObject {
 /// Specifically, the levels data.
 /// Todo: Update the types for codomain used in the EtlUnit, EtlField vs Source contexts.
 ///
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, JsonSchema)]
 pub struct EtlObject {
+    /// `None` for documents written before the field existed (version 0);
+    /// see `Migrations` for the upgrade.
+    #[serde(
+        rename = "schemaVersion",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub schema_version: Option<u32>,
     #[serde(rename = "etlFields")]
     pub etl_fields: HashMap<String, EtlField>,
     #[serde(rename = "etlUnits")]
     pub etl_units: HashMap<String, EtlUnit>,
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+impl EtlObject {
+    /// The `schemaVersion` this model reads and writes
+    pub const SCHEMA_VERSION: u32 = 1;
+
+    /// The JSON Schema (draft 7) of `etlObj.json` as this model reads and
+    /// writes it; the `purpose`, `type` and `source-type` tags select the
+    /// variant.
+    pub fn json_schema() -> serde_json::Value {
+        let schema = SchemaSettings::draft07()
+            .into_generator()
+            .into_root_schema_for::<EtlObject>();
+        serde_json::to_value(schema).expect("schemas are json")
+    }
 }
 
 /// implement fmt::Display for  EtlUnit, show the enum variant, the codomain,
@@ -63,7 +90,7 @@ impl fmt::Display for EtlUnit {
     }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, JsonSchema)]
 #[serde(tag = "type")]
 pub enum EtlUnit {
     #[serde(rename = "quality")]
@@ -76,17 +103,66 @@ pub enum EtlUnit {
 
 pub type Name = String;
 
+/// A single value or a list; the frontend writes `"etl-unit"` both ways.
+/// Writes back in the shape it was read, and derefs to a slice either way.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, JsonSchema)]
+#[serde(untagged)]
+pub enum OneOrMany<T> {
+    One(T),
+    Many(Vec<T>),
+}
+
+impl<T> OneOrMany<T> {
+    pub fn as_slice(&self) -> &[T] {
+        match self {
+            OneOrMany::One(value) => std::slice::from_ref(value),
+            OneOrMany::Many(values) => values,
+        }
+    }
+    pub fn into_vec(self) -> Vec<T> {
+        match self {
+            OneOrMany::One(value) => vec![value],
+            OneOrMany::Many(values) => values,
+        }
+    }
+}
+
+impl<T> std::ops::Deref for OneOrMany<T> {
+    type Target = [T];
+    fn deref(&self) -> &[T] {
+        self.as_slice()
+    }
+}
+
+impl<T> From<T> for OneOrMany<T> {
+    fn from(value: T) -> Self {
+        OneOrMany::One(value)
+    }
+}
+
+impl<T> From<Vec<T>> for OneOrMany<T> {
+    fn from(values: Vec<T>) -> Self {
+        OneOrMany::Many(values)
+    }
+}
+
+/// The keys of a json object that the model has no field for; kept so
+/// that what the frontend adds is written back as it was read.
+pub type Extra = serde_json::Map<String, serde_json::Value>;
+
 /// The codomain is the namesake for the EtlUnit. Each name references a EtlField.
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, JsonSchema)]
 pub struct EtlUnitQuality {
     pub subject: Name,
     pub codomain: Name,
     #[serde(rename = "codomain-reducer")]
     pub codomain_reducer: Reducer,
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
 /// The codomain is the namesake for the EtlUnit. Each name references a EtlField.
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, JsonSchema)]
 pub struct EtlUnitMeasurement {
     pub subject: Name,
     pub codomain: Name,
@@ -96,16 +172,39 @@ pub struct EtlUnitMeasurement {
     pub mspan: Name,
     #[serde(rename = "slicing-reducer")]
     pub slicing_reducer: Reducer,
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 // EtlUnitSubject
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, JsonSchema)]
 pub struct EtlUnitSubject {
     pub subject: Name,
     pub codomain: Name,
+    /// `None` when the key is absent, `Some(None)` when it is `null` (as
+    /// the frontend writes it); written back the same way
+    #[serde(
+        rename = "codomain-reducer",
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "present"
+    )]
+    #[schemars(with = "Option<Reducer>")]
+    pub codomain_reducer: Option<Option<Reducer>>,
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+/// For a key that may be `null`: a present key is `Some`, even when null
+fn present<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    T::deserialize(deserializer).map(Some)
 }
 
 /// Enum to represent different kinds of EtlFields based on the purpose
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, JsonSchema)]
 #[serde(tag = "purpose")]
 pub enum EtlField {
     #[serde(rename = "subject")]
@@ -222,59 +321,70 @@ impl fmt::Display for EtlField {
 }
 
 /// Structs for each kind of EtlField (see enum). They all have a sources property.
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, JsonSchema)]
 pub struct SubjectField {
     pub idx: u32,
     pub name: Name,
     pub format: Option<String>,
     pub sources: Vec<Source>,
+    #[serde(flatten)]
+    pub extra: Extra,
 }
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, JsonSchema)]
 pub struct QualityField {
     pub idx: u32,
     pub name: Name,
     #[serde(rename = "etl-unit")]
-    pub etl_unit: Vec<Name>,
+    pub etl_unit: OneOrMany<Name>,
     pub format: Option<String>,
+    #[serde(rename = "null-value-expansion")]
     pub null_value_expansion: Option<String>,
     #[serde(rename = "map-weights")]
     pub map_weights: MapWeights,
+    #[serde(rename = "map-files")]
     pub map_files: Option<HashMap<String, String>>,
     pub sources: Vec<Source>,
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, JsonSchema)]
 pub struct MCompField {
     pub idx: u32,
     pub name: Name,
     #[serde(rename = "etl-unit")]
-    pub etl_unit: Vec<Name>,
+    pub etl_unit: OneOrMany<Name>,
     pub format: Option<String>,
     #[serde(rename = "map-weights")]
     pub map_weights: MapWeights,
+    #[serde(rename = "map-files")]
     pub map_files: Option<HashMap<String, String>>,
     pub sources: Vec<Source>,
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, JsonSchema)]
 pub struct MSpanField {
     pub idx: u32,
     pub name: Name,
     #[serde(rename = "etl-unit")]
-    pub etl_unit: Vec<Name>,
+    pub etl_unit: OneOrMany<Name>,
     pub format: Option<String>,
     pub time: Time,
     #[serde(rename = "levels-mspan")]
     pub levels_mspan: Vec<Range>,
     pub sources: Vec<Source>,
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, JsonSchema)]
 pub struct MValueField {
     pub idx: u32,
     pub name: Name,
     #[serde(rename = "etl-unit")]
-    pub etl_unit: Vec<Name>,
+    pub etl_unit: OneOrMany<Name>,
     pub format: Option<String>,
     #[serde(rename = "null-value-expansion")]
     pub null_value_expansion: Option<String>,
@@ -289,84 +399,108 @@ pub struct MValueField {
     #[serde(rename = "slicing-reducer")]
     pub slicing_reducer: Reducer,
     pub sources: Vec<Source>,
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, JsonSchema)]
 pub struct MapSymbols {
     #[serde(rename = "arrows")]
     pub arrows: HashMap<String, String>,
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, JsonSchema)]
 pub struct LevelsMspan {
     #[serde(rename = "rangeStart")]
     pub range_start: i64,
     #[serde(rename = "rangeLength")]
     pub range_length: i64,
     pub reduced: bool,
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
 // struct MapImplied so that it can host either u32 or a String
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, JsonSchema)]
 pub struct MapImplied {
     pub domain: String,
     pub codomain: Codomain,
+    #[serde(flatten)]
+    pub extra: Extra,
 }
-#[derive(Debug, Serialize)]
+/// A number or a string in the json, without a tag
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+#[serde(untagged)]
 pub enum Codomain {
     Number(u32),
     Text(String),
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, JsonSchema)]
 pub struct MapWeights {
     #[serde(rename = "arrows")]
-    pub arrows: HashMap<String, f32>,
+    pub arrows: HashMap<String, f64>,
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
 // Time
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, JsonSchema)]
 pub struct Range {
     #[serde(rename = "rangeStart")]
     pub range_start: u32,
     #[serde(rename = "rangeLength")]
     pub range_length: u32,
     pub reduced: bool,
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, JsonSchema)]
 pub struct Time {
     pub interval: Interval,
     pub reference: Reference,
+    #[serde(flatten)]
+    pub extra: Extra,
 }
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, JsonSchema)]
 pub struct Interval {
     pub unit: String,
     pub count: u32,
+    #[serde(flatten)]
+    pub extra: Extra,
 }
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, JsonSchema)]
 pub struct Reference {
     pub idx: u32,
     pub value: String,
     #[serde(rename = "isoFormat")]
     pub iso_format: String,
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
 pub type Filename = String;
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, JsonSchema)]
 pub struct MapFiles {
     #[serde(rename = "arrows")]
     pub arrows: HashMap<Filename, String>,
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, JsonSchema)]
 pub struct Level {
     pub count: u32,
     pub value: String,
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
 pub enum Purpose {
     #[serde(rename = "subject")]
     SUBJECT,
@@ -380,7 +514,7 @@ pub enum Purpose {
     MVALUE,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, JsonSchema)]
 pub enum Reducer {
     FIRST,
     LAST,
@@ -389,7 +523,7 @@ pub enum Reducer {
     MIN,
     MAX,
 }
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, JsonSchema)]
 #[serde(tag = "source-type")]
 pub enum Source {
     #[serde(rename = "RAW")]
@@ -400,16 +534,27 @@ pub enum Source {
     Wide(SourceWide),
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, JsonSchema)]
 pub struct SourceRaw {
     pub enabled: bool,
     #[serde(rename = "header-idx")]
     pub header_idx: u32,
-    #[serde(rename = "header-name")]
-    pub header_name: String,
+    #[serde(
+        rename = "header-name",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub header_name: Option<String>,
+    #[serde(
+        rename = "default-name",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub default_name: Option<String>,
     #[serde(rename = "field-alias")]
     pub field_alias: String,
     pub purpose: Purpose,
+    #[serde(rename = "null-value")]
     pub null_value: Option<serde_json::Value>,
     pub format: Option<String>,
     #[serde(rename = "map-symbols")]
@@ -423,14 +568,17 @@ pub struct SourceRaw {
     pub codomain_reducer: Option<Reducer>,
     #[serde(rename = "map-weights")]
     pub map_weights: Option<MapWeights>,
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, JsonSchema)]
 pub struct SourceImplied {
     pub enabled: bool,
     #[serde(rename = "field-alias")]
     pub field_alias: String,
     pub purpose: Purpose,
+    #[serde(rename = "null-value")]
     pub null_value: Option<serde_json::Value>,
     pub format: Option<String>,
     pub nlevels: u32, // constant 2
@@ -443,9 +591,11 @@ pub struct SourceImplied {
     pub slicing_reducer: Option<Reducer>,
     #[serde(rename = "map-weights")]
     pub map_weights: Option<MapWeights>,
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, JsonSchema)]
 pub struct SourceWide {
     pub enabled: bool,
     #[serde(rename = "header-idx")]
@@ -455,6 +605,7 @@ pub struct SourceWide {
     #[serde(rename = "field-alias")]
     pub field_alias: String,
     pub purpose: Purpose,
+    #[serde(rename = "null-value")]
     pub null_value: Option<serde_json::Value>,
     pub format: Option<String>,
     #[serde(rename = "map-symbols")]
@@ -468,6 +619,8 @@ pub struct SourceWide {
     pub codomain_reducer: Option<Reducer>,
     #[serde(rename = "map-weights")]
     pub map_weights: Option<MapWeights>,
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
 impl fmt::Display for Source {
@@ -498,6 +651,209 @@ impl fmt::Display for Source {
     }
 }
 
+impl fmt::Display for Purpose {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let purpose = match self {
+            Purpose::SUBJECT => "subject",
+            Purpose::QUALITY => "quality",
+            Purpose::MCOMP => "mcomp",
+            Purpose::MSPAN => "mspan",
+            Purpose::MVALUE => "mvalue",
+        };
+        f.write_str(purpose)
+    }
+}
+
+impl EtlField {
+    /// The `purpose` tag of the field
+    pub fn purpose(&self) -> Purpose {
+        match self {
+            EtlField::Subject(_) => Purpose::SUBJECT,
+            EtlField::Quality(_) => Purpose::QUALITY,
+            EtlField::MComp(_) => Purpose::MCOMP,
+            EtlField::MSpan(_) => Purpose::MSPAN,
+            EtlField::MValue(_) => Purpose::MVALUE,
+        }
+    }
+    pub fn idx(&self) -> u32 {
+        match self {
+            EtlField::Subject(field) => field.idx,
+            EtlField::Quality(field) => field.idx,
+            EtlField::MComp(field) => field.idx,
+            EtlField::MSpan(field) => field.idx,
+            EtlField::MValue(field) => field.idx,
+        }
+    }
+    /// The units the field belongs to; none for a subject field
+    pub fn etl_unit(&self) -> &[Name] {
+        match self {
+            EtlField::Subject(_) => &[],
+            EtlField::Quality(field) => &field.etl_unit,
+            EtlField::MComp(field) => &field.etl_unit,
+            EtlField::MSpan(field) => &field.etl_unit,
+            EtlField::MValue(field) => &field.etl_unit,
+        }
+    }
+    pub fn sources(&self) -> &[Source] {
+        match self {
+            EtlField::Subject(field) => &field.sources,
+            EtlField::Quality(field) => &field.sources,
+            EtlField::MComp(field) => &field.sources,
+            EtlField::MSpan(field) => &field.sources,
+            EtlField::MValue(field) => &field.sources,
+        }
+    }
+}
+
+impl Source {
+    pub fn purpose(&self) -> Purpose {
+        match self {
+            Source::Raw(raw) => raw.purpose,
+            Source::Implied(implied) => implied.purpose,
+            Source::Wide(wide) => wide.purpose,
+        }
+    }
+}
+
+/// A reference in an `EtlObject` that does not hold; see `EtlObject::validate`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Violation {
+    /// `role` is the unit property naming the field: codomain, subject,
+    /// mspan or mcomps
+    UnknownField {
+        unit: Name,
+        role: &'static str,
+        field: Name,
+    },
+    /// The `etl-unit` of a field names no unit
+    UnknownUnit { field: Name, unit: Name },
+    /// A measurement unit whose `mspan` is not an mspan field
+    MissingMSpan { unit: Name },
+    /// Fields sharing an `idx`, sorted
+    DuplicateIdx { idx: u32, fields: Vec<Name> },
+    /// The source at `source` (its index in `sources`) has a `purpose`
+    /// other than the field's
+    PurposeMismatch {
+        field: Name,
+        source: usize,
+        expected: Purpose,
+        found: Purpose,
+    },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Violation::UnknownField { unit, role, field } => {
+                write!(f, "unit {unit:?}: the {role} {field:?} is not a field")
+            }
+            Violation::UnknownUnit { field, unit } => {
+                write!(f, "field {field:?}: the etl-unit {unit:?} is not a unit")
+            }
+            Violation::MissingMSpan { unit } => {
+                write!(f, "unit {unit:?}: measurements need an mspan field")
+            }
+            Violation::DuplicateIdx { idx, fields } => {
+                write!(f, "idx {idx} is used by {}", fields.join(", "))
+            }
+            Violation::PurposeMismatch {
+                field,
+                source,
+                expected,
+                found,
+            } => write!(
+                f,
+                "field {field:?}: source {source} has purpose {found}, expected {expected}"
+            ),
+        }
+    }
+}
+
+impl EtlObject {
+    /// Every reference between fields and units that does not hold, in the
+    /// order of the field and unit names; empty when the object is
+    /// consistent.
+    pub fn validate(&self) -> Vec<Violation> {
+        let mut violations = Vec::new();
+
+        let mut units = self.etl_units.iter().collect::<Vec<_>>();
+        units.sort_by_key(|(name, _)| *name);
+        for (name, unit) in units {
+            let (subject, codomain) = match unit {
+                EtlUnit::Quality(quality) => (&quality.subject, &quality.codomain),
+                EtlUnit::Measurement(measurement) => (&measurement.subject, &measurement.codomain),
+                EtlUnit::Subject(subject) => (&subject.subject, &subject.codomain),
+            };
+            let mut refs = vec![("subject", subject), ("codomain", codomain)];
+            if let EtlUnit::Measurement(measurement) = unit {
+                refs.extend(measurement.mcomps.iter().map(|mcomp| ("mcomps", mcomp)));
+                match self.etl_fields.get(&measurement.mspan) {
+                    Some(EtlField::MSpan(_)) => {}
+                    Some(_) => violations.push(Violation::MissingMSpan { unit: name.clone() }),
+                    None if measurement.mspan.is_empty() => {
+                        violations.push(Violation::MissingMSpan { unit: name.clone() })
+                    }
+                    None => refs.push(("mspan", &measurement.mspan)),
+                }
+            }
+            violations.extend(
+                refs.into_iter()
+                    .filter(|(_, field)| !self.etl_fields.contains_key(*field))
+                    .map(|(role, field)| Violation::UnknownField {
+                        unit: name.clone(),
+                        role,
+                        field: field.clone(),
+                    }),
+            );
+        }
+
+        let mut fields = self.etl_fields.iter().collect::<Vec<_>>();
+        fields.sort_by_key(|(name, _)| *name);
+        for (name, field) in &fields {
+            violations.extend(
+                field
+                    .etl_unit()
+                    .iter()
+                    .filter(|unit| !self.etl_units.contains_key(*unit))
+                    .map(|unit| Violation::UnknownUnit {
+                        field: name.to_string(),
+                        unit: unit.clone(),
+                    }),
+            );
+            let expected = field.purpose();
+            violations.extend(
+                field
+                    .sources()
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, source)| source.purpose() != expected)
+                    .map(|(source, found)| Violation::PurposeMismatch {
+                        field: name.to_string(),
+                        source,
+                        expected,
+                        found: found.purpose(),
+                    }),
+            );
+        }
+
+        let mut by_idx = std::collections::BTreeMap::<u32, Vec<Name>>::new();
+        for (name, field) in &fields {
+            by_idx
+                .entry(field.idx())
+                .or_default()
+                .push(name.to_string());
+        }
+        violations.extend(
+            by_idx
+                .into_iter()
+                .filter(|(_, fields)| fields.len() > 1)
+                .map(|(idx, fields)| Violation::DuplicateIdx { idx, fields }),
+        );
+
+        violations
+    }
+}
+
 impl<'de> Deserialize<'de> for Codomain {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
@@ -601,5 +957,229 @@ mod tests {
         let etl_object: EtlObject = serde_json::from_str(json_str).unwrap();
 
         assert!(etl_object.etl_fields.contains_key("in network"));
+        match &etl_object.etl_fields["in network"] {
+            EtlField::Quality(quality) => {
+                assert_eq!(quality.etl_unit, OneOrMany::One("in network".to_string()));
+                assert_eq!(quality.etl_unit.as_slice(), ["in network"]);
+            }
+            other => panic!("{}", other),
+        }
+        match &etl_object.etl_units["NPI Number"] {
+            EtlUnit::Subject(subject) => assert_eq!(subject.codomain_reducer, Some(None)),
+            other => panic!("{}", other),
+        }
+    }
+
+    #[test]
+    fn etl_unit_one_or_many() {
+        let one: OneOrMany<Name> = serde_json::from_str(r#""NRx""#).unwrap();
+        let many: OneOrMany<Name> = serde_json::from_str(r#"["NRx", "TRx"]"#).unwrap();
+
+        assert_eq!(&*one, ["NRx"]);
+        assert_eq!(many.len(), 2);
+        assert_eq!(serde_json::to_string(&one).unwrap(), r#""NRx""#);
+        assert_eq!(serde_json::to_string(&many).unwrap(), r#"["NRx","TRx"]"#);
+        assert!(serde_json::from_str::<OneOrMany<Name>>("3").is_err());
+    }
+
+    #[test]
+    fn subject_codomain_reducer() {
+        for json in [
+            r#"{"subject":"npi","codomain":"npi"}"#,
+            r#"{"subject":"npi","codomain":"npi","codomain-reducer":null}"#,
+            r#"{"subject":"npi","codomain":"npi","codomain-reducer":"FIRST"}"#,
+        ] {
+            let unit: EtlUnitSubject = serde_json::from_str(json).unwrap();
+            assert_eq!(serde_json::to_string(&unit).unwrap(), json);
+        }
+    }
+
+    fn subject_field(idx: u32, name: &str) -> EtlField {
+        EtlField::Subject(SubjectField {
+            idx,
+            name: name.to_string(),
+            format: None,
+            sources: Vec::new(),
+            extra: Extra::new(),
+        })
+    }
+
+    fn mcomp_field(idx: u32, name: &str, etl_unit: &str) -> EtlField {
+        EtlField::MComp(MCompField {
+            idx,
+            name: name.to_string(),
+            etl_unit: vec![etl_unit.to_string()].into(),
+            format: None,
+            map_weights: MapWeights {
+                arrows: HashMap::new(),
+                extra: Extra::new(),
+            },
+            map_files: None,
+            sources: Vec::new(),
+            extra: Extra::new(),
+        })
+    }
+
+    fn implied_source(purpose: Purpose) -> Source {
+        Source::Implied(SourceImplied {
+            enabled: true,
+            field_alias: "q".to_string(),
+            purpose,
+            null_value: None,
+            format: None,
+            nlevels: 2,
+            filename: "/shared/datafiles/q.csv".to_string(),
+            map_implied: MapImplied {
+                domain: "q".to_string(),
+                codomain: Codomain::Number(1),
+                extra: Extra::new(),
+            },
+            codomain_reducer: None,
+            slicing_reducer: None,
+            map_weights: None,
+            extra: Extra::new(),
+        })
+    }
+
+    fn measurement(subject: &str, codomain: &str, mcomps: &[&str], mspan: &str) -> EtlUnit {
+        EtlUnit::Measurement(EtlUnitMeasurement {
+            subject: subject.to_string(),
+            codomain: codomain.to_string(),
+            codomain_reducer: Reducer::SUM,
+            mcomps: mcomps.iter().map(|mcomp| mcomp.to_string()).collect(),
+            mspan: mspan.to_string(),
+            slicing_reducer: Reducer::SUM,
+            extra: Extra::new(),
+        })
+    }
+
+    #[test]
+    fn validate_consistent() {
+        let etl_object = EtlObject {
+            schema_version: None,
+            etl_fields: HashMap::from([("npi".to_string(), subject_field(0, "npi"))]),
+            extra: Extra::new(),
+            etl_units: HashMap::from([(
+                "npi".to_string(),
+                EtlUnit::Subject(EtlUnitSubject {
+                    subject: "npi".to_string(),
+                    codomain: "npi".to_string(),
+                    codomain_reducer: None,
+                    extra: Extra::new(),
+                }),
+            )]),
+        };
+
+        assert!(etl_object.validate().is_empty());
+    }
+
+    #[test]
+    fn validate_reports_every_violation() {
+        let mut subject = subject_field(1, "q");
+        if let EtlField::Subject(field) = &mut subject {
+            field.sources = vec![
+                implied_source(Purpose::SUBJECT),
+                implied_source(Purpose::QUALITY),
+            ];
+        }
+        let etl_object = EtlObject {
+            schema_version: None,
+            etl_fields: HashMap::from([
+                ("npi".to_string(), subject_field(0, "npi")),
+                ("q".to_string(), subject),
+                ("spec".to_string(), mcomp_field(1, "spec", "units")),
+            ]),
+            extra: Extra::new(),
+            etl_units: HashMap::from([
+                (
+                    "units".to_string(),
+                    measurement("npi", "units", &["spec"], "spec"),
+                ),
+                (
+                    "visits".to_string(),
+                    measurement("npi", "visits", &["month"], "time"),
+                ),
+            ]),
+        };
+
+        let violations = etl_object.validate();
+
+        assert_eq!(
+            violations,
+            vec![
+                Violation::MissingMSpan {
+                    unit: "units".to_string()
+                },
+                Violation::UnknownField {
+                    unit: "units".to_string(),
+                    role: "codomain",
+                    field: "units".to_string(),
+                },
+                Violation::UnknownField {
+                    unit: "visits".to_string(),
+                    role: "codomain",
+                    field: "visits".to_string(),
+                },
+                Violation::UnknownField {
+                    unit: "visits".to_string(),
+                    role: "mcomps",
+                    field: "month".to_string(),
+                },
+                Violation::UnknownField {
+                    unit: "visits".to_string(),
+                    role: "mspan",
+                    field: "time".to_string(),
+                },
+                Violation::PurposeMismatch {
+                    field: "q".to_string(),
+                    source: 1,
+                    expected: Purpose::SUBJECT,
+                    found: Purpose::QUALITY,
+                },
+                Violation::DuplicateIdx {
+                    idx: 1,
+                    fields: vec!["q".to_string(), "spec".to_string()],
+                },
+            ]
+        );
+        assert_eq!(
+            violations[0].to_string(),
+            "unit \"units\": measurements need an mspan field"
+        );
+    }
+
+    #[test]
+    fn validate_unknown_unit() {
+        let etl_object = EtlObject {
+            schema_version: None,
+            etl_fields: HashMap::from([
+                ("npi".to_string(), subject_field(0, "npi")),
+                ("spec".to_string(), mcomp_field(1, "spec", "units")),
+            ]),
+            extra: Extra::new(),
+            etl_units: HashMap::from([(
+                "npi".to_string(),
+                EtlUnit::Subject(EtlUnitSubject {
+                    subject: "npi".to_string(),
+                    codomain: "npi".to_string(),
+                    codomain_reducer: None,
+                    extra: Extra::new(),
+                }),
+            )]),
+        };
+
+        let violations = etl_object.validate();
+
+        assert_eq!(
+            violations,
+            vec![Violation::UnknownUnit {
+                field: "spec".to_string(),
+                unit: "units".to_string(),
+            }]
+        );
+        assert_eq!(
+            violations[0].to_string(),
+            "field \"spec\": the etl-unit \"units\" is not a unit"
+        );
     }
 }
@@ -0,0 +1,356 @@
+//! Read and write the project `EtlObject` (`etlObj.json` in the diamonds)
+//!
+//! A loaded `EtlObject` remembers the ETag it was read with, and saving it
+//! only succeeds when the file has not changed since; otherwise the error
+//! carries both versions so that the caller can merge them and save again.
+//!
+//! Documents of an older `schemaVersion` are upgraded as they are read;
+//! `migrate_etl_object` also saves the upgrade.
+//!
+use std::error::Error as StdError;
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+
+use futures::stream::TryStreamExt;
+use serde::Serialize;
+use serde_json::Value;
+use tracing::info;
+
+use crate::client::{Client, Request};
+use crate::error::{self, Error, Result};
+use crate::etl_obj::EtlObject;
+use crate::migrate::{MigrationReport, Migrations};
+use crate::object_path::{ObjectPath, ProjectLayout};
+use crate::response::{Body, Method};
+
+const JSON: &str = "application/json";
+
+impl Client {
+    /// Where the `EtlObject` of the project is saved
+    pub fn etl_object_path(&self, project_id: impl AsRef<str>) -> Result<ObjectPath> {
+        ProjectLayout::new(project_id)?.diamonds(&self.config.etl_obj_filename)
+    }
+
+    /// The `EtlObject` with the ETag of the file it was read from, upgraded
+    /// to the latest schema version (the file is left as it is)
+    pub async fn load_etl_object(
+        &self,
+        project_id: impl AsRef<str>,
+    ) -> Result<Versioned<EtlObject>> {
+        let key = self.etl_object_path(project_id)?.key();
+        let (etl_object, _) = self.load_versioned(&key).await?;
+        Ok(etl_object)
+    }
+
+    /// Upgrade the saved `EtlObject` to the latest schema version; saved
+    /// (as `save_etl_object` does) only when a migration ran.
+    pub async fn migrate_etl_object(&self, project_id: impl AsRef<str>) -> Result<MigrationReport> {
+        let key = self.etl_object_path(project_id)?.key();
+        let (mut etl_object, report) = self.load_versioned(&key).await?;
+        if !report.is_empty() {
+            self.save_versioned(&key, &mut etl_object).await?;
+            info!("Migrated {} from {}", key, report);
+        }
+        Ok(report)
+    }
+
+    /// Save only if the file is still the version that was loaded (or, for
+    /// a `Versioned::new`, if there is no file yet); the ETag is updated on
+    /// success. A `Kind::Conflict` error carries an `EtlObjectConflict`
+    /// with both versions. Saved with the latest `schemaVersion`.
+    pub async fn save_etl_object(
+        &self,
+        project_id: impl AsRef<str>,
+        etl_object: &mut Versioned<EtlObject>,
+    ) -> Result<()> {
+        let key = self.etl_object_path(project_id)?.key();
+        self.save_versioned(&key, etl_object).await
+    }
+
+    async fn save_versioned(&self, key: &str, etl_object: &mut Versioned<EtlObject>) -> Result<()> {
+        etl_object.value.schema_version = Some(EtlObject::SCHEMA_VERSION);
+        let data = to_json(&etl_object.value, key)?;
+        let req = Request::new(Method::Write(Body::Bytes(data.into())), key, json());
+        let req = match &etl_object.e_tag {
+            Some(e_tag) => req.with_if_match(e_tag),
+            None => req.with_if_none_match("*"),
+        };
+        match self.request(req).await {
+            Ok(response) => {
+                if let Body::Put(output) = response.into_body() {
+                    etl_object.e_tag = output.e_tag;
+                }
+                Ok(())
+            }
+            Err(e) if e.is_conflict() => {
+                let (theirs, _) = self.load_versioned(key).await?;
+                let conflict = EtlObjectConflict {
+                    ours: etl_object.value.clone(),
+                    theirs,
+                };
+                Err(
+                    error::conflict(conflict, "EtlObject changed since it was loaded")
+                        .with_key(key),
+                )
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Save whatever is there now, with the latest `schemaVersion`
+    pub async fn overwrite_etl_object(
+        &self,
+        project_id: impl AsRef<str>,
+        etl_object: &EtlObject,
+    ) -> Result<()> {
+        let key = self.etl_object_path(project_id)?.key();
+        let etl_object = EtlObject {
+            schema_version: Some(EtlObject::SCHEMA_VERSION),
+            ..etl_object.clone()
+        };
+        let data = to_json(&etl_object, &key)?;
+        self.write(&key, data, json()).await?;
+        Ok(())
+    }
+
+    async fn load_versioned(&self, key: &str) -> Result<(Versioned<EtlObject>, MigrationReport)> {
+        let stream = self.read_stream(key).await?;
+        let e_tag = stream.e_tag().map(str::to_string);
+        let chunks: Vec<_> = stream.try_collect().await?;
+        let (value, report) = from_json(&chunks.concat(), key)?;
+        Ok((Versioned { value, e_tag }, report))
+    }
+}
+
+fn json() -> Option<String> {
+    Some(JSON.to_string())
+}
+
+/// A value and the ETag of the file it was read from; derefs to the value.
+#[derive(Debug, Clone)]
+pub struct Versioned<T> {
+    value: T,
+    e_tag: Option<String>,
+}
+
+impl<T> Versioned<T> {
+    /// A value that has not been saved yet
+    pub fn new(value: T) -> Self {
+        Versioned { value, e_tag: None }
+    }
+    pub fn value(&self) -> &T {
+        &self.value
+    }
+    pub fn into_value(self) -> T {
+        self.value
+    }
+    /// As returned by S3, quotes included; `None` until saved
+    pub fn e_tag(&self) -> Option<&str> {
+        self.e_tag.as_deref()
+    }
+}
+
+impl<T> Deref for Versioned<T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T> DerefMut for Versioned<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.value
+    }
+}
+
+impl<T: fmt::Display> fmt::Display for Versioned<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.value.fmt(f)
+    }
+}
+
+/// The source of the error when a save finds the file changed: what was
+/// being saved and what is there now. Saving `theirs` (after merging)
+/// retries against the current version.
+#[derive(Debug, Clone)]
+pub struct EtlObjectConflict {
+    pub ours: EtlObject,
+    pub theirs: Versioned<EtlObject>,
+}
+
+impl EtlObjectConflict {
+    /// The conflict behind a `save_etl_object` error, if that was the cause
+    pub fn from_error(err: &Error) -> Option<&EtlObjectConflict> {
+        err.source()?.downcast_ref()
+    }
+}
+
+impl fmt::Display for EtlObjectConflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "the EtlObject is now at {}",
+            self.theirs.e_tag().unwrap_or("an unknown version")
+        )
+    }
+}
+
+impl StdError for EtlObjectConflict {}
+
+/// The `EtlObject`, after `Migrations::default()`
+pub(crate) fn from_json(data: &[u8], key: &str) -> Result<(EtlObject, MigrationReport)> {
+    let mut doc: Value = serde_json::from_slice(data)
+        .map_err(|e| error::malformed_data(e, "EtlObject from json").with_key(key))?;
+    let report = Migrations::default()
+        .upgrade(&mut doc)
+        .map_err(|e| e.with_key(key))?;
+    let etl_object = serde_json::from_value(doc)
+        .map_err(|e| error::malformed_data(e, "EtlObject from json").with_key(key))?;
+    Ok((etl_object, report))
+}
+
+pub(crate) fn to_json<T: Serialize>(value: &T, key: &str) -> Result<Vec<u8>> {
+    serde_json::to_vec(value)
+        .map_err(|e| error::malformed_data(e, "EtlObject to json").with_key(key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory_store::MemoryStore;
+
+    const PROJECT: &str = "f2afe5c4-92f0-41c4-a8a6-c0d85ed0b9fd";
+
+    fn etl_object(field: &str) -> EtlObject {
+        let json = format!(
+            r#"{{"etlFields": {{"{field}": {{"purpose": "subject", "idx": 0, "name": "{field}", "sources": []}}}}, "etlUnits": {{}}}}"#
+        );
+        from_json(json.as_bytes(), "k").unwrap().0
+    }
+
+    #[tokio::test]
+    async fn saves_are_conditional() {
+        let client = Client::with_store(MemoryStore::new("test-bucket"));
+        client
+            .save_etl_object(PROJECT, &mut Versioned::new(etl_object("a")))
+            .await
+            .unwrap();
+
+        let mut analyst = client.load_etl_object(PROJECT).await.unwrap();
+        let mut other = client.load_etl_object(PROJECT).await.unwrap();
+        assert!(analyst.e_tag().is_some());
+
+        *analyst = etl_object("b");
+        client.save_etl_object(PROJECT, &mut analyst).await.unwrap();
+        assert_ne!(analyst.e_tag(), other.e_tag());
+
+        *other = etl_object("c");
+        let err = client
+            .save_etl_object(PROJECT, &mut other)
+            .await
+            .unwrap_err();
+        assert!(err.is_conflict());
+        let conflict = EtlObjectConflict::from_error(&err).unwrap();
+        assert!(conflict.ours.etl_fields.contains_key("c"));
+        assert!(conflict.theirs.etl_fields.contains_key("b"));
+        assert_eq!(conflict.theirs.e_tag(), analyst.e_tag());
+
+        // merged onto the current version, the save goes through
+        let mut merged = conflict.theirs.clone();
+        merged.etl_fields.extend(conflict.ours.etl_fields.clone());
+        client.save_etl_object(PROJECT, &mut merged).await.unwrap();
+        assert_eq!(
+            client
+                .load_etl_object(PROJECT)
+                .await
+                .unwrap()
+                .etl_fields
+                .len(),
+            2
+        );
+    }
+
+    #[tokio::test]
+    async fn migrates_older_versions() {
+        let client = Client::with_store(MemoryStore::new("test-bucket"));
+        let key = client.etl_object_path(PROJECT).unwrap().key();
+        let version_0 = br#"{"etlFields": {"in network": {"purpose": "quality", "idx": 1, "name": "in network", "etl-unit": "in network", "format": null, "null-value-expansion": null, "map-weights": {"arrows": {}}, "map-files": null, "sources": []}}, "etlUnits": {}}"#;
+        client.write(&key, &version_0[..], None).await.unwrap();
+
+        let loaded = client.load_etl_object(PROJECT).await.unwrap();
+        assert_eq!(loaded.schema_version, Some(EtlObject::SCHEMA_VERSION));
+        assert_eq!(loaded.etl_fields["in network"].etl_unit(), ["in network"]);
+
+        let report = client.migrate_etl_object(PROJECT).await.unwrap();
+        assert_eq!(report.applied, vec!["schema-version"]);
+        let saved: Value = serde_json::from_slice(&client.read_bytes(&key).await.unwrap()).unwrap();
+        assert_eq!(saved["schemaVersion"], 1);
+        // written back in the shape it was read
+        assert_eq!(saved["etlFields"]["in network"]["etl-unit"], "in network");
+
+        assert!(client.migrate_etl_object(PROJECT).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn saves_the_latest_version() {
+        let client = Client::with_store(MemoryStore::new("test-bucket"));
+        let key = client.etl_object_path(PROJECT).unwrap().key();
+        let unversioned = EtlObject {
+            schema_version: None,
+            ..etl_object("a")
+        };
+        async fn saved_version(client: &Client, key: &str) -> Value {
+            let data = client.read_bytes(key).await.unwrap();
+            serde_json::from_slice::<Value>(&data).unwrap()["schemaVersion"].take()
+        }
+
+        let mut new = Versioned::new(unversioned.clone());
+        client.save_etl_object(PROJECT, &mut new).await.unwrap();
+        assert_eq!(new.schema_version, Some(EtlObject::SCHEMA_VERSION));
+        assert_eq!(saved_version(&client, &key).await, 1);
+        let loaded = client.load_etl_object(PROJECT).await.unwrap();
+        assert_eq!(loaded.schema_version, Some(1));
+        assert!(client.migrate_etl_object(PROJECT).await.unwrap().is_empty());
+
+        client.delete(&key).await.unwrap();
+        client
+            .overwrite_etl_object(PROJECT, &unversioned)
+            .await
+            .unwrap();
+        assert_eq!(saved_version(&client, &key).await, 1);
+    }
+
+    #[tokio::test]
+    async fn new_objects_do_not_replace_a_file() {
+        let client = Client::with_store(MemoryStore::new("test-bucket"));
+        client
+            .overwrite_etl_object(PROJECT, &etl_object("a"))
+            .await
+            .unwrap();
+
+        let err = client
+            .save_etl_object(PROJECT, &mut Versioned::new(etl_object("b")))
+            .await
+            .unwrap_err();
+
+        assert!(err.is_conflict());
+    }
+
+    #[test]
+    fn malformed_json_has_the_key() {
+        let err = from_json(b"{\"etlFields\": []}", "p/etlObj.json").unwrap_err();
+
+        assert!(err.is_malformed_data());
+        assert_eq!(err.key(), Some("p/etlObj.json"));
+    }
+
+    #[test]
+    fn json_roundtrip() {
+        let json = br#"{"etlFields": {}, "etlUnits": {}}"#;
+        let (etl_object, _) = from_json(json, "k").unwrap();
+
+        let data = to_json(&etl_object, "k").unwrap();
+
+        assert!(from_json(&data, "k").unwrap().0.etl_fields.is_empty());
+    }
+}
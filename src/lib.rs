@@ -1,14 +1,75 @@
+//! S3 client for the TNC app.
+//!
+//! ```no_run
+//! use s3_client::{Body, Client, ListOptions, Method, Request};
+//! use tower_service::Service;
+//!
+//! # async fn run() -> s3_client::Result<()> {
+//! let mut client = Client::builder().build().await?;
+//! let req = Request::new(Method::List(ListOptions::new()), "my-project/shared", None);
+//! if let Body::Files(files) = client.call(req).await?.into_body() {
+//!     println!("{:?}", files.contents());
+//! }
+//! # Ok(())
+//! # }
+//! ```
 #[path = "error.rs"]
 pub mod error;
 
 #[path = "etl-obj.rs"]
 pub mod etl_obj;
 
-pub use error::{Error, Kind};
+pub use error::{Error, Kind, Result};
 
-// #[path = "response.rs"]
-// mod response;
-// #[path = "sync_wrapper.rs"]
-// mod sync_wrapper;
-// #[path = "client.rs"]
-// mod client;
+#[path = "batch.rs"]
+mod batch;
+#[path = "client.rs"]
+mod client;
+#[path = "data_file.rs"]
+mod data_file;
+#[path = "download.rs"]
+mod download;
+#[path = "etl_io.rs"]
+mod etl_io;
+#[path = "list.rs"]
+mod list;
+#[path = "local_store.rs"]
+mod local_store;
+#[path = "memory_store.rs"]
+mod memory_store;
+#[path = "migrate.rs"]
+mod migrate;
+#[path = "object_path.rs"]
+mod object_path;
+#[path = "presign.rs"]
+mod presign;
+#[path = "response.rs"]
+mod response;
+#[path = "retry.rs"]
+mod retry;
+#[path = "s3_store.rs"]
+mod s3_store;
+#[path = "settings.rs"]
+mod settings;
+#[path = "store.rs"]
+mod store;
+#[path = "sync_wrapper.rs"]
+mod sync_wrapper;
+#[path = "upload.rs"]
+mod upload;
+
+pub use batch::Batch;
+pub use client::{Client, ClientBuilder, Request, ResponseFuture};
+pub use data_file::DataFile;
+pub use download::{ByteRange, ReadStream, ResumableDownload};
+pub use etl_io::{EtlObjectConflict, Versioned};
+pub use list::{ListEntry, ListOptions};
+pub use local_store::LocalStore;
+pub use memory_store::MemoryStore;
+pub use migrate::{Migration, MigrationReport, Migrations};
+pub use object_path::{Area, ObjectPath, ProjectId, ProjectLayout};
+pub use response::{Body, Method, Response};
+pub use retry::{Retry, RetryLayer, RetryPolicy};
+pub use s3_store::S3Store;
+pub use store::{GetOptions, ObjectStore, PutOptions, StoreFuture};
+pub use upload::{MultipartUpload, UploadOptions, MIN_PART_SIZE};
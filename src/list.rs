@@ -0,0 +1,205 @@
+//! Listing that follows the continuation tokens; `list_objects_v2` returns
+//! at most 1000 keys per call.
+//!
+use aws_sdk_s3::operation::list_objects_v2::ListObjectsV2Output;
+use futures::stream::{self, BoxStream, StreamExt, TryStreamExt};
+
+use crate::client::{Client, Request};
+use crate::data_file::DataFile;
+use crate::error::{self, Result};
+use crate::response::{Body, Method};
+
+/// Parameters of a `Method::List` request
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ListOptions {
+    pub(crate) max_keys: Option<i32>,
+    pub(crate) start_after: Option<String>,
+    pub(crate) delimiter: Option<String>,
+    pub(crate) continuation_token: Option<String>,
+}
+
+impl ListOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// The page size (S3 caps it at 1000); use `take` on the stream to limit
+    /// the total
+    pub fn max_keys(mut self, max_keys: i32) -> Self {
+        self.max_keys = Some(max_keys);
+        self
+    }
+    /// Only keys that sort after this one
+    pub fn start_after(mut self, key: impl Into<String>) -> Self {
+        self.start_after = Some(key.into());
+        self
+    }
+    /// Group the keys below the next delimiter into a `ListEntry::Prefix`
+    /// ("directory") instead of listing them
+    pub fn delimiter(mut self, delimiter: impl Into<String>) -> Self {
+        self.delimiter = Some(delimiter.into());
+        self
+    }
+    /// `delimiter("/")`
+    pub fn directories(self) -> Self {
+        self.delimiter("/")
+    }
+
+    /// The options for the page after this one, if any
+    pub(crate) fn next_page(self, page: &ListObjectsV2Output) -> Option<ListOptions> {
+        match (page.is_truncated(), page.next_continuation_token()) {
+            (true, Some(token)) => Some(ListOptions {
+                continuation_token: Some(token.to_string()),
+                ..self
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// An item of a listing
+#[derive(Debug, Clone, PartialEq)]
+pub enum ListEntry {
+    File(DataFile),
+    /// A common prefix when listing with a delimiter; ends with the delimiter
+    Prefix(String),
+}
+
+impl ListEntry {
+    /// The object key, or the prefix
+    pub fn key(&self) -> &str {
+        match self {
+            ListEntry::File(file) => &file.key,
+            ListEntry::Prefix(prefix) => prefix,
+        }
+    }
+
+    /// The files then the prefixes of a page
+    pub(crate) fn from_page(page: ListObjectsV2Output) -> Vec<ListEntry> {
+        let prefixes = page
+            .common_prefixes()
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|prefix| prefix.prefix())
+            .map(|prefix| ListEntry::Prefix(prefix.to_string()));
+        DataFile::from_listing(&page)
+            .into_iter()
+            .map(ListEntry::File)
+            .chain(prefixes)
+            .collect()
+    }
+}
+
+impl Client {
+    /// Every page of the listing of keys that start with `prefix`
+    pub fn list_pages(
+        &self,
+        prefix: impl AsRef<str>,
+        options: ListOptions,
+    ) -> BoxStream<'static, Result<ListObjectsV2Output>> {
+        let client = self.clone();
+        let prefix = prefix.as_ref().to_string();
+
+        stream::try_unfold(Some(options), move |options| {
+            let client = client.clone();
+            let prefix = prefix.clone();
+            async move {
+                let options = match options {
+                    Some(options) => options,
+                    None => return Ok(None),
+                };
+                let req = Request::new(Method::List(options.clone()), &prefix, None);
+                let page = match client.request(req).await?.into_body() {
+                    Body::Files(page) => page,
+                    other => {
+                        return Err(error::internal(
+                            format!("{:?}", other),
+                            "Expected a listing",
+                        ))
+                    }
+                };
+                let next = options.next_page(&page);
+                Ok(Some((page, next)))
+            }
+        })
+        .boxed()
+    }
+
+    /// Every entry of the listing of keys that start with `prefix`
+    pub fn list_stream(
+        &self,
+        prefix: impl AsRef<str>,
+        options: ListOptions,
+    ) -> BoxStream<'static, Result<ListEntry>> {
+        self.list_pages(prefix, options)
+            .map_ok(|page| stream::iter(ListEntry::from_page(page).into_iter().map(Ok)))
+            .try_flatten()
+            .boxed()
+    }
+
+    /// Every file with a key that starts with `prefix`
+    pub async fn list_data_files(&self, prefix: impl AsRef<str>) -> Result<Vec<DataFile>> {
+        self.list_stream(prefix, ListOptions::default())
+            .try_filter_map(|entry| async move {
+                match entry {
+                    ListEntry::File(file) => Ok(Some(file)),
+                    ListEntry::Prefix(_) => Ok(None),
+                }
+            })
+            .try_collect()
+            .await
+    }
+
+    /// Every key that starts with `prefix`
+    pub async fn list_keys(&self, prefix: impl AsRef<str>) -> Result<Vec<String>> {
+        self.list_stream(prefix, ListOptions::default())
+            .map_ok(|entry| entry.key().to_string())
+            .try_collect()
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aws_sdk_s3::types::{CommonPrefix, Object};
+
+    fn page(keys: &[&str], prefixes: &[&str], next: Option<&str>) -> ListObjectsV2Output {
+        let mut page = ListObjectsV2Output::builder()
+            .is_truncated(next.is_some())
+            .set_next_continuation_token(next.map(str::to_string));
+        for key in keys {
+            page = page.contents(Object::builder().key(*key).size(1).build());
+        }
+        for prefix in prefixes {
+            page = page.common_prefixes(CommonPrefix::builder().prefix(*prefix).build());
+        }
+        page.build()
+    }
+
+    #[test]
+    fn follows_continuation_token() {
+        let options = ListOptions::new().max_keys(2).start_after("a");
+
+        let next = options
+            .clone()
+            .next_page(&page(&["b", "c"], &[], Some("token")));
+
+        assert_eq!(
+            next,
+            Some(ListOptions {
+                continuation_token: Some("token".to_string()),
+                ..options.clone()
+            })
+        );
+        assert_eq!(options.next_page(&page(&["d"], &[], None)), None);
+    }
+
+    #[test]
+    fn entries_of_a_page() {
+        let entries = ListEntry::from_page(page(&["p/a.json"], &["p/shared/"], None));
+
+        let keys = entries.iter().map(ListEntry::key).collect::<Vec<_>>();
+        assert_eq!(keys, vec!["p/a.json", "p/shared/"]);
+        assert!(matches!(entries[1], ListEntry::Prefix(_)));
+    }
+}
@@ -0,0 +1,346 @@
+use std::collections::HashMap;
+use std::io::{self, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use aws_sdk_s3::operation::get_object::GetObjectOutput;
+use aws_sdk_s3::operation::list_buckets::ListBucketsOutput;
+use aws_sdk_s3::operation::list_objects_v2::ListObjectsV2Output;
+use aws_sdk_s3::operation::put_object::PutObjectOutput;
+use aws_sdk_s3::primitives::DateTime;
+use bytes::Bytes;
+use futures::future::FutureExt;
+use md5::{Digest, Md5};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio::sync::Mutex;
+
+use crate::data_file::DataFile;
+use crate::error::{self, Error, Result};
+use crate::list::ListOptions;
+use crate::store::{self, GetOptions, ObjectStore, PutOptions, StoreFuture};
+
+/// Marks a file being written; never listed
+const TMP_MARKER: &str = ".s3tmp-";
+
+/// A bucket in a local directory: the key `a/b.json` is the file
+/// `{root}/a/b.json`. The bucket is named after the directory.
+///
+/// The ETag is the md5 of the contents, as for an object S3 got in one
+/// piece. It is computed by `head` and `get` and remembered while the size
+/// and modification time of the file stay the same; listings are made from
+/// the file metadata only, so they have the ETags already known and none
+/// for the other files. Writes through the store (and its clones) are
+/// serialized, so a conditional put checks and writes in one step; other
+/// processes writing the directory are not seen.
+#[derive(Debug, Clone)]
+pub struct LocalStore {
+    bucket: String,
+    root: PathBuf,
+    /// Held while writing
+    lock: Arc<Mutex<()>>,
+    e_tags: Arc<std::sync::Mutex<HashMap<PathBuf, KnownETag>>>,
+}
+
+/// The ETag of a file of that size and modification time
+#[derive(Debug, Clone)]
+struct KnownETag {
+    len: u64,
+    modified: Option<SystemTime>,
+    e_tag: String,
+}
+
+impl LocalStore {
+    /// The directory must exist
+    pub fn new(root: impl Into<PathBuf>) -> Result<Self> {
+        let root = root.into();
+        if !root.is_dir() {
+            return Err(error::builder(format!(
+                "Not a directory: {}",
+                root.display()
+            )));
+        }
+        let bucket = root
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| "local".to_string());
+        Ok(LocalStore {
+            bucket,
+            root,
+            lock: Arc::default(),
+            e_tags: Arc::default(),
+        })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn path(&self, key: &str) -> Result<PathBuf> {
+        store::validate_key(key)?;
+        Ok(self.root.join(key))
+    }
+
+    /// The path and metadata of the file of `key`
+    async fn metadata(&self, key: &str) -> Result<(PathBuf, std::fs::Metadata)> {
+        let path = self.path(key)?;
+        let metadata = tokio::fs::metadata(&path)
+            .await
+            .map_err(|e| io_error(e, key, "Error reading file"))?;
+        if !metadata.is_file() {
+            return Err(store::not_found(key));
+        }
+        Ok((path, metadata))
+    }
+
+    /// The file with its ETag, hashing the contents unless they are known
+    async fn file(&self, key: &str) -> Result<DataFile> {
+        let (path, metadata) = self.metadata(key).await?;
+        let e_tag = match self.known_e_tag(&path, &metadata) {
+            Some(e_tag) => e_tag,
+            None => {
+                let e_tag = md5_file(&path)
+                    .await
+                    .map_err(|e| io_error(e, key, "Error reading file"))?;
+                // unless the file changed while it was read
+                if let Ok(now) = tokio::fs::metadata(&path).await {
+                    if now.len() == metadata.len()
+                        && now.modified().ok() == metadata.modified().ok()
+                    {
+                        self.remember(&path, &metadata, &e_tag);
+                    }
+                }
+                e_tag
+            }
+        };
+        Ok(data_file(&self.bucket, key, &metadata, Some(e_tag)))
+    }
+
+    fn known_e_tag(&self, path: &Path, metadata: &std::fs::Metadata) -> Option<String> {
+        let e_tags = self.e_tags.lock().unwrap();
+        let known = e_tags.get(path)?;
+        let same = known.len == metadata.len() && known.modified == metadata.modified().ok();
+        same.then(|| known.e_tag.clone())
+    }
+
+    fn remember(&self, path: &Path, metadata: &std::fs::Metadata, e_tag: &str) {
+        let known = KnownETag {
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+            e_tag: e_tag.to_string(),
+        };
+        self.e_tags
+            .lock()
+            .unwrap()
+            .insert(path.to_path_buf(), known);
+    }
+
+    /// Call with the lock held
+    async fn write(&self, key: &str, data: &[u8]) -> Result<PutObjectOutput> {
+        let path = self.path(key)?;
+        let tmp = path.with_file_name(format!(
+            ".{}{}{:016x}",
+            path.file_name().unwrap_or_default().to_string_lossy(),
+            TMP_MARKER,
+            fastrand::u64(..)
+        ));
+        let write = async {
+            if let Some(dir) = path.parent() {
+                tokio::fs::create_dir_all(dir).await?;
+            }
+            tokio::fs::write(&tmp, data).await?;
+            tokio::fs::rename(&tmp, &path).await
+        };
+        if let Err(e) = write.await {
+            let _ = tokio::fs::remove_file(&tmp).await;
+            return Err(io_error(e, key, "Error writing file"));
+        }
+        let e_tag = store::e_tag(data);
+        match tokio::fs::metadata(&path).await {
+            Ok(metadata) => self.remember(&path, &metadata, &e_tag),
+            Err(_) => {
+                self.e_tags.lock().unwrap().remove(&path);
+            }
+        }
+        Ok(PutObjectOutput::builder().e_tag(e_tag).build())
+    }
+
+    /// Every file below the root, sorted by key
+    fn walk(&self) -> io::Result<Vec<DataFile>> {
+        let mut files = Vec::new();
+        let mut dirs = vec![self.root.clone()];
+        while let Some(dir) = dirs.pop() {
+            for entry in std::fs::read_dir(&dir)? {
+                let entry = entry?;
+                let metadata = entry.metadata()?;
+                let path = entry.path();
+                if metadata.is_dir() {
+                    dirs.push(path);
+                    continue;
+                }
+                let name = entry.file_name();
+                if !metadata.is_file() || name.to_string_lossy().contains(TMP_MARKER) {
+                    continue;
+                }
+                let key = path
+                    .strip_prefix(&self.root)
+                    .unwrap_or(&path)
+                    .components()
+                    .map(|c| c.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/");
+                let e_tag = self.known_e_tag(&path, &metadata);
+                files.push(data_file(&self.bucket, &key, &metadata, e_tag));
+            }
+        }
+        files.sort_by(|a, b| a.key.cmp(&b.key));
+        Ok(files)
+    }
+}
+
+impl ObjectStore for LocalStore {
+    fn bucket(&self) -> &str {
+        &self.bucket
+    }
+
+    fn list_buckets(&self) -> StoreFuture<'_, ListBucketsOutput> {
+        async move { Ok(store::list_buckets_output(&self.bucket)) }.boxed()
+    }
+
+    fn get<'a>(&'a self, key: &'a str, options: GetOptions) -> StoreFuture<'a, GetObjectOutput> {
+        async move {
+            let file = self.file(key).await?;
+            store::check_get(key, &file, &options)?;
+            let range = store::byte_range(key, options.range, file.size as u64)?;
+            let path = self.path(key)?;
+            let read = async {
+                let mut f = tokio::fs::File::open(&path).await?;
+                let mut data = Vec::new();
+                match &range {
+                    None => {
+                        f.read_to_end(&mut data).await?;
+                    }
+                    Some((range, _)) => {
+                        f.seek(SeekFrom::Start(range.start)).await?;
+                        f.take(range.end - range.start)
+                            .read_to_end(&mut data)
+                            .await?;
+                    }
+                }
+                Ok(data)
+            };
+            let data = read
+                .await
+                .map_err(|e| io_error(e, key, "Error reading file"))?;
+            let content_range = range.map(|(_, content_range)| content_range);
+            Ok(store::ranged_output(
+                data.into(),
+                content_range,
+                &file,
+                options.content_type,
+            ))
+        }
+        .boxed()
+    }
+
+    fn put<'a>(
+        &'a self,
+        key: &'a str,
+        data: Bytes,
+        options: PutOptions,
+    ) -> StoreFuture<'a, PutObjectOutput> {
+        async move {
+            let _lock = self.lock.lock().await;
+            if options.if_match.is_some() || options.if_none_match.is_some() {
+                let e_tag = match self.file(key).await {
+                    Ok(file) => file.etag,
+                    Err(e) if e.is_not_found() => None,
+                    Err(e) => return Err(e),
+                };
+                store::check_put(key, e_tag.as_deref(), &options)?;
+            }
+            self.write(key, &data).await
+        }
+        .boxed()
+    }
+
+    fn list<'a>(
+        &'a self,
+        prefix: &'a str,
+        options: ListOptions,
+    ) -> StoreFuture<'a, ListObjectsV2Output> {
+        async move {
+            let this = self.clone();
+            let files = tokio::task::spawn_blocking(move || this.walk())
+                .await
+                .map_err(|e| error::internal(e, "Error listing files"))?
+                .map_err(|e| io_error(e, prefix, "Error listing files"))?;
+            Ok(store::list_page(&self.bucket, &files, prefix, options))
+        }
+        .boxed()
+    }
+
+    fn head<'a>(&'a self, key: &'a str) -> StoreFuture<'a, DataFile> {
+        self.file(key).boxed()
+    }
+
+    fn delete<'a>(&'a self, key: &'a str) -> StoreFuture<'a, ()> {
+        async move {
+            let path = self.path(key)?;
+            self.e_tags.lock().unwrap().remove(&path);
+            match tokio::fs::remove_file(&path).await {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => {
+                    Err(io_error(e, key, "Error deleting file"))
+                }
+                _ => Ok(()),
+            }
+        }
+        .boxed()
+    }
+
+    fn copy<'a>(&'a self, from: &'a str, to: &'a str) -> StoreFuture<'a, ()> {
+        async move {
+            let (path, _) = self.metadata(from).await?;
+            let data = tokio::fs::read(&path)
+                .await
+                .map_err(|e| io_error(e, from, "Error reading file"))?;
+            let _lock = self.lock.lock().await;
+            self.write(to, &data).await.map(|_| ())
+        }
+        .boxed()
+    }
+}
+
+fn data_file(
+    bucket: &str,
+    key: &str,
+    metadata: &std::fs::Metadata,
+    e_tag: Option<String>,
+) -> DataFile {
+    let mut file = DataFile::new(bucket, key, metadata.len() as i64);
+    file.last_modified = metadata.modified().ok().map(DateTime::from);
+    file.etag = e_tag;
+    file.storage_class = Some("STANDARD".to_string());
+    file
+}
+
+/// The quoted md5 of the file, read in chunks
+async fn md5_file(path: &Path) -> io::Result<String> {
+    let mut file = tokio::fs::File::open(path).await?;
+    let mut md5 = Md5::new();
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        match file.read(&mut buf).await? {
+            0 => break,
+            n => md5.update(&buf[..n]),
+        }
+    }
+    Ok(format!("\"{:x}\"", md5.finalize()))
+}
+
+fn io_error(e: io::Error, key: &str, msg: &str) -> Error {
+    match e.kind() {
+        io::ErrorKind::NotFound => store::not_found(key),
+        _ => error::internal(e, msg).with_key(key),
+    }
+}
@@ -0,0 +1,150 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+
+use aws_sdk_s3::operation::get_object::GetObjectOutput;
+use aws_sdk_s3::operation::list_buckets::ListBucketsOutput;
+use aws_sdk_s3::operation::list_objects_v2::ListObjectsV2Output;
+use aws_sdk_s3::operation::put_object::PutObjectOutput;
+use bytes::Bytes;
+use futures::future::{self, FutureExt};
+
+use crate::data_file::DataFile;
+use crate::list::ListOptions;
+use crate::store::{self, GetOptions, ObjectStore, PutOptions, StoreFuture};
+
+/// A bucket in memory; clones share the objects. For tests.
+#[derive(Debug, Clone)]
+pub struct MemoryStore {
+    bucket: String,
+    objects: Arc<RwLock<BTreeMap<String, Stored>>>,
+}
+
+#[derive(Debug, Clone)]
+struct Stored {
+    data: Bytes,
+    file: DataFile,
+    content_type: Option<String>,
+}
+
+impl MemoryStore {
+    pub fn new(bucket: impl Into<String>) -> Self {
+        MemoryStore {
+            bucket: bucket.into(),
+            objects: Arc::default(),
+        }
+    }
+
+    /// Every key, sorted
+    pub fn keys(&self) -> Vec<String> {
+        self.objects().keys().cloned().collect()
+    }
+
+    fn objects(&self) -> std::sync::RwLockReadGuard<'_, BTreeMap<String, Stored>> {
+        self.objects.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn objects_mut(&self) -> std::sync::RwLockWriteGuard<'_, BTreeMap<String, Stored>> {
+        self.objects.write().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn stored(&self, key: &str) -> crate::Result<Stored> {
+        store::validate_key(key)?;
+        self.objects()
+            .get(key)
+            .cloned()
+            .ok_or_else(|| store::not_found(key))
+    }
+
+    fn insert(&self, key: &str, data: Bytes, content_type: Option<String>) -> PutObjectOutput {
+        self.insert_into(&mut self.objects_mut(), key, data, content_type)
+    }
+
+    fn insert_into(
+        &self,
+        objects: &mut BTreeMap<String, Stored>,
+        key: &str,
+        data: Bytes,
+        content_type: Option<String>,
+    ) -> PutObjectOutput {
+        let mut file = DataFile::new(&self.bucket, key, data.len() as i64);
+        file.etag = Some(store::e_tag(&data));
+        file.last_modified = Some(store::now());
+        file.storage_class = Some("STANDARD".to_string());
+        let e_tag = file.etag.clone();
+        objects.insert(
+            key.to_string(),
+            Stored {
+                data,
+                file,
+                content_type,
+            },
+        );
+        PutObjectOutput::builder().set_e_tag(e_tag).build()
+    }
+}
+
+impl ObjectStore for MemoryStore {
+    fn bucket(&self) -> &str {
+        &self.bucket
+    }
+
+    fn list_buckets(&self) -> StoreFuture<'_, ListBucketsOutput> {
+        future::ready(Ok(store::list_buckets_output(&self.bucket))).boxed()
+    }
+
+    fn get<'a>(&'a self, key: &'a str, options: GetOptions) -> StoreFuture<'a, GetObjectOutput> {
+        let result = self.stored(key).and_then(|stored| {
+            store::get_output(key, stored.data, &stored.file, stored.content_type, options)
+        });
+        future::ready(result).boxed()
+    }
+
+    fn put<'a>(
+        &'a self,
+        key: &'a str,
+        data: Bytes,
+        options: PutOptions,
+    ) -> StoreFuture<'a, PutObjectOutput> {
+        let result = store::validate_key(key).and_then(|()| {
+            // checked and written under one lock
+            let mut objects = self.objects_mut();
+            let e_tag = objects.get(key).and_then(|stored| stored.file.etag.clone());
+            store::check_put(key, e_tag.as_deref(), &options)?;
+            Ok(self.insert_into(&mut objects, key, data, options.content_type))
+        });
+        future::ready(result).boxed()
+    }
+
+    fn list<'a>(
+        &'a self,
+        prefix: &'a str,
+        options: ListOptions,
+    ) -> StoreFuture<'a, ListObjectsV2Output> {
+        let files = self
+            .objects()
+            .values()
+            .map(|stored| stored.file.clone())
+            .collect::<Vec<_>>();
+        future::ready(Ok(store::list_page(&self.bucket, &files, prefix, options))).boxed()
+    }
+
+    fn head<'a>(&'a self, key: &'a str) -> StoreFuture<'a, DataFile> {
+        future::ready(self.stored(key).map(|stored| stored.file)).boxed()
+    }
+
+    fn delete<'a>(&'a self, key: &'a str) -> StoreFuture<'a, ()> {
+        let result = store::validate_key(key).map(|()| {
+            self.objects_mut().remove(key);
+        });
+        future::ready(result).boxed()
+    }
+
+    fn copy<'a>(&'a self, from: &'a str, to: &'a str) -> StoreFuture<'a, ()> {
+        let result = store::validate_key(to)
+            .and_then(|()| self.stored(from))
+            .map(|stored| {
+                self.insert(to, stored.data, stored.content_type);
+            });
+        future::ready(result).boxed()
+    }
+}
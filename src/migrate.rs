@@ -0,0 +1,240 @@
+//! Upgrade `etlObj.json` documents written by older versions of the model.
+//!
+//! The version is the `schemaVersion` key (a document without one is
+//! version 0). Each `Migration` rewrites the json of one version into the
+//! next; `Migrations::upgrade` runs them in order up to
+//! `EtlObject::SCHEMA_VERSION` and reports which ones ran.
+//!
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde_json::Value;
+
+use crate::error::{self, Result};
+use crate::etl_obj::EtlObject;
+
+const VERSION_KEY: &str = "schemaVersion";
+
+/// One step: turns a document of version `from` into version `from + 1`
+#[derive(Clone)]
+pub struct Migration {
+    from: u32,
+    name: &'static str,
+    migrate: fn(&mut Value) -> std::result::Result<(), String>,
+}
+
+impl Migration {
+    pub fn new(
+        from: u32,
+        name: &'static str,
+        migrate: fn(&mut Value) -> std::result::Result<(), String>,
+    ) -> Self {
+        Migration {
+            from,
+            name,
+            migrate,
+        }
+    }
+    pub fn from(&self) -> u32 {
+        self.from
+    }
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+}
+
+impl fmt::Debug for Migration {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Migration")
+            .field("from", &self.from)
+            .field("name", &self.name)
+            .finish()
+    }
+}
+
+/// The registry of migrations, by the version they upgrade from.
+/// `Migrations::default()` has the ones of this crate; version 1 only adds
+/// `schemaVersion`, so going 0 -> 1 leaves the rest of the document as it
+/// is.
+#[derive(Debug, Clone)]
+pub struct Migrations {
+    steps: BTreeMap<u32, Migration>,
+    latest: u32,
+}
+
+impl Default for Migrations {
+    fn default() -> Self {
+        Migrations::new(EtlObject::SCHEMA_VERSION).register(Migration::new(
+            0,
+            "schema-version",
+            |_| Ok(()),
+        ))
+    }
+}
+
+impl Migrations {
+    /// No migrations; `upgrade` brings documents to `latest`
+    pub fn new(latest: u32) -> Self {
+        Migrations {
+            steps: BTreeMap::new(),
+            latest,
+        }
+    }
+
+    /// Replaces the migration with the same `from`
+    pub fn register(mut self, migration: Migration) -> Self {
+        self.steps.insert(migration.from, migration);
+        self
+    }
+
+    pub fn latest(&self) -> u32 {
+        self.latest
+    }
+
+    /// Run the migrations from the version of `doc` to the latest, setting
+    /// `schemaVersion` after each one. A document newer than the latest,
+    /// or a version with no migration, is a `Kind::MalformedData` error.
+    pub fn upgrade(&self, doc: &mut Value) -> Result<MigrationReport> {
+        let from = version(doc)?;
+        if from > self.latest {
+            return Err(error::malformed_data(
+                format!("version {} is newer than {}", from, self.latest),
+                "EtlObject schemaVersion",
+            ));
+        }
+        let mut report = MigrationReport {
+            from,
+            to: from,
+            applied: Vec::new(),
+        };
+        while report.to < self.latest {
+            let step = self.steps.get(&report.to).ok_or_else(|| {
+                error::malformed_data(
+                    format!("no migration from version {}", report.to),
+                    "EtlObject schemaVersion",
+                )
+            })?;
+            (step.migrate)(doc).map_err(|e| {
+                error::malformed_data(e, format!("EtlObject migration {}", step.name))
+            })?;
+            report.to += 1;
+            set_version(doc, report.to);
+            report.applied.push(step.name);
+        }
+        Ok(report)
+    }
+}
+
+/// What `Migrations::upgrade` did; `applied` is empty when the document
+/// was already at the latest version.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MigrationReport {
+    pub from: u32,
+    pub to: u32,
+    pub applied: Vec<&'static str>,
+}
+
+impl MigrationReport {
+    pub fn is_empty(&self) -> bool {
+        self.applied.is_empty()
+    }
+}
+
+impl fmt::Display for MigrationReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "at version {}", self.to);
+        }
+        write!(
+            f,
+            "version {} to {}: {}",
+            self.from,
+            self.to,
+            self.applied.join(", ")
+        )
+    }
+}
+
+fn version(doc: &Value) -> Result<u32> {
+    match doc.get(VERSION_KEY) {
+        None | Some(Value::Null) => Ok(0),
+        Some(value) => value
+            .as_u64()
+            .and_then(|version| u32::try_from(version).ok())
+            .ok_or_else(|| error::malformed_data(format!("{}", value), "EtlObject schemaVersion")),
+    }
+}
+
+fn set_version(doc: &mut Value, version: u32) {
+    if let Some(doc) = doc.as_object_mut() {
+        doc.insert(VERSION_KEY.to_string(), version.into());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn upgrades_version_0() {
+        let mut doc = json!({
+            "etlFields": {"in network": {"etl-unit": "in network"}},
+            "etlUnits": {}
+        });
+
+        let report = Migrations::default().upgrade(&mut doc).unwrap();
+
+        assert_eq!(report.from, 0);
+        assert_eq!(report.to, EtlObject::SCHEMA_VERSION);
+        assert_eq!(report.applied, vec!["schema-version"]);
+        assert_eq!(doc["schemaVersion"], 1);
+        assert_eq!(doc["etlFields"]["in network"]["etl-unit"], "in network");
+    }
+
+    #[test]
+    fn latest_is_left_alone() {
+        let mut doc = json!({"schemaVersion": 1, "etlFields": {}, "etlUnits": {}});
+        let before = doc.clone();
+
+        let report = Migrations::default().upgrade(&mut doc).unwrap();
+
+        assert!(report.is_empty());
+        assert_eq!(doc, before);
+    }
+
+    #[test]
+    fn runs_registered_steps_in_order() {
+        fn rename(doc: &mut Value) -> std::result::Result<(), String> {
+            let units = doc["units"].take();
+            doc["etlUnits"] = units;
+            Ok(())
+        }
+        let migrations = Migrations::new(3)
+            .register(Migration::new(2, "rename-units", rename))
+            .register(Migration::new(1, "noop", |_| Ok(())));
+        let mut doc = json!({"schemaVersion": 1, "units": {}});
+
+        let report = migrations.upgrade(&mut doc).unwrap();
+
+        assert_eq!(report.applied, vec!["noop", "rename-units"]);
+        assert_eq!(report.to_string(), "version 1 to 3: noop, rename-units");
+        assert_eq!(doc["etlUnits"], json!({}));
+        assert_eq!(doc["schemaVersion"], 3);
+    }
+
+    #[test]
+    fn unknown_versions() {
+        let newer = Migrations::default().upgrade(&mut json!({"schemaVersion": 9}));
+        assert!(newer.unwrap_err().is_malformed_data());
+
+        let gap = Migrations::new(2).upgrade(&mut json!({"schemaVersion": 1}));
+        assert!(gap.unwrap_err().is_malformed_data());
+
+        let failing = Migrations::new(1).register(Migration::new(0, "fails", |_| {
+            Err("etlFields is not an object".to_string())
+        }));
+        let failed = failing.upgrade(&mut json!({"etlFields": []}));
+        assert!(failed.unwrap_err().is_malformed_data());
+    }
+}
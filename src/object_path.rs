@@ -0,0 +1,362 @@
+//! The key layout of a TNC project in the bucket:
+//!
+//! ```text
+//! {project}/{file}                                Area::Project
+//! {project}/shared/{file}                         Area::Shared
+//! {project}/shared/diamonds/{project}/{file}      Area::Diamonds
+//! {project}/shared/datafiles/{file}               Area::Datafiles
+//! {project}/users/{user}/{file}                   Area::User
+//! ```
+//!
+//! The project id is a UUID.
+//!
+use std::fmt;
+use std::str::FromStr;
+
+use uuid::Uuid;
+
+use crate::error::{self, Error, Result};
+
+const SHARED: &str = "shared";
+const DIAMONDS: &str = "diamonds";
+const DATAFILES: &str = "datafiles";
+const USERS: &str = "users";
+
+/// Validated project id; displays as the hyphenated lowercase UUID
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ProjectId(Uuid);
+
+impl ProjectId {
+    /// Only the hyphenated lowercase form: a key with another form of the
+    /// UUID would not be the key it displays as.
+    pub fn parse(project_id: impl AsRef<str>) -> Result<ProjectId> {
+        let project_id = project_id.as_ref();
+        let uuid = Uuid::parse_str(project_id).map_err(|e| {
+            error::malformed_data(e, format!("Project id is not a UUID: {}", project_id))
+        })?;
+        if uuid.hyphenated().to_string() != project_id {
+            return Err(error::malformed_data(
+                format!("expected {}", uuid.hyphenated()),
+                format!(
+                    "Project id is not a lowercase hyphenated UUID: {}",
+                    project_id
+                ),
+            ));
+        }
+        Ok(ProjectId(uuid))
+    }
+}
+
+impl fmt::Display for ProjectId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.hyphenated().fmt(f)
+    }
+}
+
+impl FromStr for ProjectId {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        ProjectId::parse(s)
+    }
+}
+
+/// Where in the project a file lives
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Area {
+    Project,
+    Shared,
+    Diamonds,
+    Datafiles,
+    User(String),
+}
+
+/// The prefixes of one project
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ProjectLayout {
+    project: ProjectId,
+}
+
+impl ProjectLayout {
+    pub fn new(project_id: impl AsRef<str>) -> Result<ProjectLayout> {
+        Ok(ProjectLayout {
+            project: ProjectId::parse(project_id)?,
+        })
+    }
+
+    pub fn project(&self) -> &ProjectId {
+        &self.project
+    }
+
+    /// The key prefix of the area, with a trailing '/'
+    pub fn prefix(&self, area: &Area) -> String {
+        let p = &self.project;
+        match area {
+            Area::Project => format!("{}/", p),
+            Area::Shared => format!("{}/{}/", p, SHARED),
+            Area::Diamonds => format!("{}/{}/{}/{}/", p, SHARED, DIAMONDS, p),
+            Area::Datafiles => format!("{}/{}/{}/", p, SHARED, DATAFILES),
+            Area::User(user) => format!("{}/{}/{}/", p, USERS, user),
+        }
+    }
+
+    /// A file in the area; the user of an `Area::User` and the filename are
+    /// checked as in `user`
+    pub fn file(&self, area: Area, filename: impl AsRef<str>) -> Result<ObjectPath> {
+        ObjectPath::build(*self, area, filename.as_ref())
+    }
+
+    pub fn diamonds(&self, filename: impl AsRef<str>) -> Result<ObjectPath> {
+        self.file(Area::Diamonds, filename)
+    }
+
+    pub fn datafiles(&self, filename: impl AsRef<str>) -> Result<ObjectPath> {
+        self.file(Area::Datafiles, filename)
+    }
+
+    pub fn shared(&self, filename: impl AsRef<str>) -> Result<ObjectPath> {
+        self.file(Area::Shared, filename)
+    }
+
+    pub fn user(&self, user: impl AsRef<str>, filename: impl AsRef<str>) -> Result<ObjectPath> {
+        self.file(Area::User(user.as_ref().to_string()), filename)
+    }
+}
+
+/// The key of a file in a project; `to_string` (or `key`) gives the S3 key.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ObjectPath {
+    layout: ProjectLayout,
+    area: Area,
+    filename: String,
+}
+
+impl ObjectPath {
+    /// A file at the root of the project
+    pub fn new(project_id: impl AsRef<str>, filename: impl AsRef<str>) -> Result<Self> {
+        ProjectLayout::new(project_id)?.file(Area::Project, filename)
+    }
+
+    fn build(layout: ProjectLayout, area: Area, filename: &str) -> Result<Self> {
+        if let Area::User(user) = &area {
+            if user.is_empty() || user.contains('/') {
+                return Err(error::builder(format!("Invalid user: {:?}", user)));
+            }
+        }
+        // if the first char is a '/' remove it
+        let filename = filename.strip_prefix('/').unwrap_or(filename);
+        if filename.is_empty() || filename.ends_with('/') {
+            return Err(error::builder(format!("Invalid filename: {:?}", filename)));
+        }
+        // a key that would parse as a file of another area
+        let first = filename.split_once('/').map(|(first, _)| first);
+        let reserved = match area {
+            Area::Project => [SHARED, USERS].as_slice(),
+            Area::Shared => [DIAMONDS, DATAFILES].as_slice(),
+            _ => [].as_slice(),
+        };
+        if first.is_some_and(|first| reserved.contains(&first)) {
+            return Err(error::builder(format!(
+                "Filename {:?} starts with the prefix of another area",
+                filename
+            )));
+        }
+        Ok(ObjectPath {
+            layout,
+            area,
+            filename: filename.to_string(),
+        })
+    }
+
+    /// The same file in another area of the project; checked as `file` is
+    pub fn in_area(self, area: Area) -> Result<Self> {
+        ObjectPath::build(self.layout, area, &self.filename)
+    }
+
+    /// Split a key into its parts
+    pub fn parse(key: impl AsRef<str>) -> Result<Self> {
+        let key = key.as_ref();
+        let malformed =
+            |msg: &str| error::malformed_data(msg.to_string(), "Not a project key").with_key(key);
+
+        let (project_id, rest) = key
+            .split_once('/')
+            .ok_or_else(|| malformed("no filename"))?;
+        let layout = ProjectLayout::new(project_id).map_err(|e| e.with_key(key))?;
+
+        let (area, filename) = match rest.split_once('/') {
+            Some((SHARED, rest)) => match rest.split_once('/') {
+                Some((DIAMONDS, rest)) => {
+                    let (owner, filename) = rest
+                        .split_once('/')
+                        .ok_or_else(|| malformed("no diamonds project"))?;
+                    if ProjectId::parse(owner).ok() != Some(layout.project) {
+                        return Err(malformed("diamonds of another project"));
+                    }
+                    (Area::Diamonds, filename)
+                }
+                Some((DATAFILES, filename)) => (Area::Datafiles, filename),
+                _ => (Area::Shared, rest),
+            },
+            Some((USERS, rest)) => {
+                let (user, filename) = rest.split_once('/').ok_or_else(|| malformed("no user"))?;
+                (Area::User(user.to_string()), filename)
+            }
+            _ => (Area::Project, rest),
+        };
+
+        ObjectPath::build(layout, area, filename).map_err(|e| e.with_key(key))
+    }
+
+    pub fn project(&self) -> &ProjectId {
+        self.layout.project()
+    }
+    pub fn layout(&self) -> &ProjectLayout {
+        &self.layout
+    }
+    pub fn area(&self) -> &Area {
+        &self.area
+    }
+    /// The path within the area
+    pub fn filename(&self) -> &str {
+        &self.filename
+    }
+    pub fn key(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl fmt::Display for ObjectPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", self.layout.prefix(&self.area), self.filename)
+    }
+}
+
+impl FromStr for ObjectPath {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        ObjectPath::parse(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROJECT: &str = "fef57333-67c0-4825-9765-5bf48f3d5f63";
+
+    #[test]
+    fn keys() {
+        let layout = ProjectLayout::new(PROJECT).unwrap();
+
+        assert_eq!(
+            layout.diamonds("etlObj.json").unwrap().key(),
+            format!("{0}/shared/diamonds/{0}/etlObj.json", PROJECT)
+        );
+        assert_eq!(
+            layout.datafiles("/dir/target_list.csv").unwrap().key(),
+            format!("{}/shared/datafiles/dir/target_list.csv", PROJECT)
+        );
+        assert_eq!(
+            layout.user("edmund", "notes.txt").unwrap().key(),
+            format!("{}/users/edmund/notes.txt", PROJECT)
+        );
+        assert_eq!(
+            ObjectPath::new(PROJECT, "a.json").unwrap().key(),
+            format!("{}/a.json", PROJECT)
+        );
+    }
+
+    #[test]
+    fn parse_roundtrip() {
+        let layout = ProjectLayout::new(PROJECT).unwrap();
+        let paths = vec![
+            ObjectPath::new(PROJECT, "a.json").unwrap(),
+            layout.shared("warehouse.json").unwrap(),
+            layout.diamonds("etlObj.json").unwrap(),
+            layout.datafiles("dir/target_list.csv").unwrap(),
+            layout.user("edmund", "notes.txt").unwrap(),
+        ];
+        for path in paths {
+            assert_eq!(ObjectPath::parse(path.key()).unwrap(), path);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_keys() {
+        assert!(ProjectId::parse("not-a-project")
+            .unwrap_err()
+            .is_malformed_data());
+        assert!(ObjectPath::parse(PROJECT).is_err());
+        let other = "f2afe5c4-92f0-41c4-a8a6-c0d85ed0b9fd";
+        assert!(ObjectPath::parse(format!("{}/shared/diamonds/{}/x", PROJECT, other)).is_err());
+        assert!(ObjectPath::new(PROJECT, "dir/").is_err());
+    }
+
+    #[test]
+    fn only_canonical_project_ids() {
+        let upper = PROJECT.to_uppercase();
+        let simple = PROJECT.replace('-', "");
+        for project_id in [upper.as_str(), &simple, &format!("urn:uuid:{}", PROJECT)] {
+            let err = ProjectId::parse(project_id).unwrap_err();
+            assert!(err.is_malformed_data(), "{}", project_id);
+            // rather than a path to another key
+            let key = format!("{}/a.json", project_id);
+            assert!(ObjectPath::parse(&key).unwrap_err().is_malformed_data());
+        }
+
+        let key = format!("{}/a.json", PROJECT);
+        assert_eq!(ObjectPath::parse(&key).unwrap().key(), key);
+    }
+
+    #[test]
+    fn moves_between_areas() {
+        let path = ObjectPath::new(PROJECT, "etlObj.json")
+            .unwrap()
+            .in_area(Area::Diamonds)
+            .unwrap();
+        assert_eq!(path.filename(), "etlObj.json");
+        assert_eq!(path.area(), &Area::Diamonds);
+
+        let layout = ProjectLayout::new(PROJECT).unwrap();
+        let shared = layout.datafiles("shared/x.csv").unwrap();
+        assert!(shared.in_area(Area::Project).unwrap_err().is_builder());
+    }
+
+    #[test]
+    fn rejects_area_prefixes() {
+        let layout = ProjectLayout::new(PROJECT).unwrap();
+
+        for filename in ["shared/x", "users/edmund/x"] {
+            let err = ObjectPath::new(PROJECT, filename).unwrap_err();
+            assert!(err.is_builder(), "{}", filename);
+        }
+        for filename in ["diamonds/x", "datafiles/x"] {
+            assert!(layout.shared(filename).unwrap_err().is_builder());
+        }
+
+        // the same names are fine further down, or as the whole filename
+        let paths = vec![
+            ObjectPath::new(PROJECT, "shared").unwrap(),
+            ObjectPath::new(PROJECT, "dir/shared/x").unwrap(),
+            layout.shared("datafiles").unwrap(),
+            layout.datafiles("shared/x.csv").unwrap(),
+            layout.user("edmund", "users/x").unwrap(),
+        ];
+        for path in paths {
+            assert_eq!(ObjectPath::parse(path.key()).unwrap(), path);
+        }
+    }
+
+    #[test]
+    fn file_checks_the_user() {
+        let layout = ProjectLayout::new(PROJECT).unwrap();
+
+        for user in ["", "a/b"] {
+            let err = layout.file(Area::User(user.to_string()), "x").unwrap_err();
+            assert!(err.is_builder());
+        }
+        assert!(layout.user("a/b", "x").is_err());
+    }
+}
@@ -0,0 +1,132 @@
+//! Presigned urls, for the browser to read or upload a file without our
+//! credentials. The url has the addressing of the client: path-style
+//! (`host/bucket/key`) or virtual-host (`bucket.host/key`).
+//!
+use std::time::Duration;
+
+use aws_sdk_s3::presigning::{PresignedRequest, PresigningConfig};
+use url::Url;
+
+use crate::client::Client;
+use crate::error::{self, Result};
+
+impl Client {
+    /// A url to GET the file until `expires_in` (at most a week) has passed
+    pub async fn presign_read(&self, key: impl AsRef<str>, expires_in: Duration) -> Result<Url> {
+        let key = key.as_ref();
+        let presigned = self
+            .inner
+            .get_object()
+            .bucket(self.bucket())
+            .key(key)
+            .presigned(presigning_config(expires_in, key)?)
+            .await
+            .map_err(|sdk_err| {
+                error::from_sdk(sdk_err)
+                    .with_msg("Error presigning read")
+                    .with_key(key)
+            })?;
+        to_url(presigned, key)
+    }
+
+    /// A url to PUT the file until `expires_in` (at most a week) has passed.
+    /// The upload must send the same `Content-Type`.
+    pub async fn presign_write(
+        &self,
+        key: impl AsRef<str>,
+        content_type: Option<String>,
+        expires_in: Duration,
+    ) -> Result<Url> {
+        let key = key.as_ref();
+        let presigned = self
+            .inner
+            .put_object()
+            .bucket(self.bucket())
+            .key(key)
+            .set_content_type(content_type)
+            .presigned(presigning_config(expires_in, key)?)
+            .await
+            .map_err(|sdk_err| {
+                error::from_sdk(sdk_err)
+                    .with_msg("Error presigning write")
+                    .with_key(key)
+            })?;
+        to_url(presigned, key)
+    }
+}
+
+fn presigning_config(expires_in: Duration, key: &str) -> Result<PresigningConfig> {
+    PresigningConfig::expires_in(expires_in)
+        .map_err(|e| error::builder(e).with_msg("Invalid expiry").with_key(key))
+}
+
+fn to_url(presigned: PresignedRequest, key: &str) -> Result<Url> {
+    let uri = presigned.uri().to_string();
+    Url::parse(&uri)
+        .map_err(|e| error::internal(e, format!("Presigned an invalid url: {}", uri)).with_key(key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn client(endpoint_url: &str, force_path_style: bool) -> Client {
+        Client::builder()
+            .bucket("luci-space")
+            .endpoint_url(endpoint_url)
+            .region("sfo3")
+            .credentials("id", "secret", None)
+            .force_path_style(force_path_style)
+            .build()
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn path_style() {
+        let client = client("http://127.0.0.1:9000", true).await;
+
+        let url = client
+            .presign_read("p/etlObj.json", Duration::from_secs(300))
+            .await
+            .unwrap();
+
+        assert_eq!(url.host_str(), Some("127.0.0.1"));
+        assert_eq!(url.path(), "/luci-space/p/etlObj.json");
+        assert!(url
+            .query_pairs()
+            .any(|(k, v)| k == "X-Amz-Expires" && v == "300"));
+    }
+
+    #[tokio::test]
+    async fn virtual_host() {
+        let client = client("https://sfo3.digitaloceanspaces.com", false).await;
+
+        let url = client
+            .presign_write(
+                "p/shared/datafiles/a.csv",
+                Some("text/csv".to_string()),
+                Duration::from_secs(60),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            url.host_str(),
+            Some("luci-space.sfo3.digitaloceanspaces.com")
+        );
+        assert_eq!(url.path(), "/p/shared/datafiles/a.csv");
+    }
+
+    #[tokio::test]
+    async fn expiry_of_more_than_a_week() {
+        let client = client("http://127.0.0.1:9000", true).await;
+
+        let err = client
+            .presign_read("p/etlObj.json", Duration::from_secs(8 * 24 * 3600))
+            .await
+            .unwrap_err();
+
+        assert!(err.is_builder());
+    }
+}
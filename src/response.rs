@@ -1,25 +1,45 @@
 use aws_sdk_s3::operation::get_object::GetObjectOutput;
 use aws_sdk_s3::operation::list_buckets::ListBucketsOutput;
 use aws_sdk_s3::operation::list_objects_v2::ListObjectsV2Output;
+use aws_sdk_s3::operation::put_object::PutObjectOutput;
+use bytes::Bytes;
+
+use crate::data_file::DataFile;
+use crate::list::ListOptions;
 
 #[derive(Debug)]
-pub(crate) struct Response {
+pub struct Response {
     pub(crate) body: Body,
 }
 
+/// The payload of a `Response` (the sdk output), or of a `Method::Write`.
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug)]
-pub(crate) enum Body {
+pub enum Body {
     Buckets(ListBucketsOutput),
     Files(ListObjectsV2Output),
     File(GetObjectOutput),
+    Put(PutObjectOutput),
+    /// The metadata of a `Method::Head`
+    Head(DataFile),
+    Bytes(Bytes),
     Empty,
 }
 
+/// What the `Request` does with the filename (the object key, or the key
+/// prefix when listing; the destination when copying). Only `Write`
+/// carries a payload.
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug)]
 pub enum Method {
-    Read(Body),
+    Read,
     Write(Body),
-    List(Body),
+    List(ListOptions),
+    ListBuckets,
+    Head,
+    Delete,
+    /// From this key
+    Copy(String),
 }
 
 impl Response {
@@ -28,6 +48,10 @@ impl Response {
         Response { body }
     }
     #[inline]
+    pub fn body(&self) -> &Body {
+        &self.body
+    }
+    #[inline]
     pub fn into_body(self) -> Body {
         self.body
     }
@@ -0,0 +1,347 @@
+//! Retry middleware for any `Service<Request>` (e.g. the `Client`):
+//!
+//! ```no_run
+//! use s3_client::{Client, RetryLayer, RetryPolicy};
+//! use tower_layer::Layer;
+//!
+//! # async fn run() -> s3_client::Result<()> {
+//! let client = Client::builder().build().await?;
+//! let client = RetryLayer::new(RetryPolicy::default().max_retries(5)).layer(client);
+//! # Ok(())
+//! # }
+//! ```
+//!
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use futures::future::poll_fn;
+use tower_layer::Layer;
+use tower_service::Service;
+use tracing::warn;
+
+use crate::client::{Request, ResponseFuture};
+use crate::error::{Error, Result};
+use crate::response::{Method, Response};
+
+/// When and how long to wait before sending a failed request again.
+///
+/// Only transient errors (see `Error::is_transient`) are retried, after a
+/// jittered exponential backoff: a random delay up to
+/// `min(max_delay, base_delay * 2^attempt)`. Writes with `If-Match` or
+/// `If-None-Match` are never sent twice: the first may have succeeded
+/// before its response was lost, and the retry would then fail its own
+/// precondition. Conditional reads are retried like any read.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    max_retries: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+    budget: Option<Duration>,
+    retry_writes: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: 3,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(5),
+            budget: None,
+            retry_writes: false,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+    pub fn base_delay(mut self, base_delay: Duration) -> Self {
+        self.base_delay = base_delay;
+        self
+    }
+    pub fn max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = max_delay;
+        self
+    }
+    /// The most time a request may spend, retries and delays included,
+    /// before the last error is returned
+    pub fn budget(mut self, budget: Duration) -> Self {
+        self.budget = Some(budget);
+        self
+    }
+    /// Retry unconditional writes whose body can be replayed (off by
+    /// default); a put of the same bytes is idempotent.
+    pub fn retry_writes(mut self, retry_writes: bool) -> Self {
+        self.retry_writes = retry_writes;
+        self
+    }
+
+    /// A copy of the request to retry with, if it may be retried
+    fn replay(&self, req: &Request) -> Option<Request> {
+        let conditional = req.if_match().is_some() || req.if_none_match().is_some();
+        match req.method() {
+            Method::Write(_) if conditional || !self.retry_writes => None,
+            _ => req.try_clone(),
+        }
+    }
+
+    /// The delay before the retry, if there is one
+    fn delay(&self, attempt: u32, started: Instant, err: &Error) -> Option<Duration> {
+        if attempt >= self.max_retries || !err.is_transient() {
+            return None;
+        }
+        let delay = self.backoff(attempt);
+        match self.budget {
+            Some(budget) if started.elapsed() + delay > budget => None,
+            _ => Some(delay),
+        }
+    }
+
+    fn backoff(&self, attempt: u32) -> Duration {
+        let ceiling = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_delay);
+        ceiling.mul_f64(fastrand::f64())
+    }
+}
+
+/// Wraps a service in `Retry`
+#[derive(Debug, Clone, Default)]
+pub struct RetryLayer {
+    policy: RetryPolicy,
+}
+
+impl RetryLayer {
+    pub fn new(policy: RetryPolicy) -> Self {
+        RetryLayer { policy }
+    }
+}
+
+impl<S> Layer<S> for RetryLayer {
+    type Service = Retry<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Retry {
+            inner,
+            policy: self.policy.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Retry<S> {
+    inner: S,
+    policy: RetryPolicy,
+}
+
+impl<S> Retry<S> {
+    pub fn new(inner: S, policy: RetryPolicy) -> Self {
+        Retry { inner, policy }
+    }
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S> Service<Request> for Retry<S>
+where
+    S: Service<Request, Response = Response, Error = Error> + Clone + Send + 'static,
+    S::Future: Send,
+{
+    type Response = Response;
+    type Error = Error;
+    type Future = ResponseFuture;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        // the clone is ready for the first call; see tower::Service docs
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let policy = self.policy.clone();
+
+        ResponseFuture::new(async move {
+            let started = Instant::now();
+            let mut req = req;
+            let mut attempt = 0;
+            loop {
+                let replay = policy.replay(&req);
+                let err = match inner.call(req).await {
+                    Err(err) => err,
+                    ok => return ok,
+                };
+                let (next, delay) = match (replay, policy.delay(attempt, started, &err)) {
+                    (Some(next), Some(delay)) => (next, delay),
+                    _ => return Err(err),
+                };
+                warn!(attempt, ?delay, "Retrying {}: {}", next.filename(), err);
+                tokio::time::sleep(delay).await;
+                poll_fn(|cx| inner.poll_ready(cx)).await?;
+                req = next;
+                attempt += 1;
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Kind;
+    use crate::response::Body;
+    use bytes::Bytes;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+
+    /// Fails with `kind` until the `fail`th call
+    #[derive(Clone)]
+    struct Flaky {
+        calls: Arc<AtomicU32>,
+        fail: u32,
+        kind: fn() -> Kind,
+    }
+
+    impl Flaky {
+        fn new(fail: u32, kind: fn() -> Kind) -> Self {
+            Flaky {
+                calls: Arc::new(AtomicU32::new(0)),
+                fail,
+                kind,
+            }
+        }
+        fn calls(&self) -> u32 {
+            self.calls.load(Ordering::SeqCst)
+        }
+    }
+
+    impl Service<Request> for Flaky {
+        type Response = Response;
+        type Error = Error;
+        type Future = ResponseFuture;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, _req: Request) -> Self::Future {
+            let call = self.calls.fetch_add(1, Ordering::SeqCst);
+            let result = if call < self.fail {
+                Err(Error::new((self.kind)(), None::<Error>))
+            } else {
+                Ok(Response::new(Body::Empty))
+            };
+            ResponseFuture::new(async move { result })
+        }
+    }
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy::new().base_delay(Duration::from_millis(1))
+    }
+
+    fn write() -> Request {
+        Request::new(Method::Write(Body::Bytes(Bytes::from("{}"))), "k", None)
+    }
+
+    #[tokio::test]
+    async fn retries_transient_errors() {
+        let flaky = Flaky::new(2, || Kind::Throttled);
+        let mut svc = RetryLayer::new(policy()).layer(flaky.clone());
+
+        svc.call(Request::new(Method::Read, "k", None))
+            .await
+            .unwrap();
+
+        assert_eq!(flaky.calls(), 3);
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_max_retries() {
+        let flaky = Flaky::new(10, || Kind::TimedOut);
+        let mut svc = Retry::new(flaky.clone(), policy().max_retries(2).retry_writes(true));
+
+        let err = svc.call(write()).await.unwrap_err();
+
+        assert!(err.is_timedout());
+        assert_eq!(flaky.calls(), 3);
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_other_errors() {
+        let flaky = Flaky::new(1, || Kind::NotFound);
+        let mut svc = Retry::new(flaky.clone(), policy());
+
+        assert!(svc.call(write()).await.unwrap_err().is_not_found());
+        assert_eq!(flaky.calls(), 1);
+    }
+
+    #[tokio::test]
+    async fn writes_are_not_retried_by_default() {
+        let flaky = Flaky::new(1, || Kind::Throttled);
+        let mut svc = Retry::new(flaky.clone(), policy());
+
+        assert!(svc.call(write()).await.unwrap_err().is_throttled());
+        assert_eq!(flaky.calls(), 1);
+
+        let mut svc = Retry::new(flaky.clone(), policy().retry_writes(true));
+        svc.call(write()).await.unwrap();
+        assert_eq!(flaky.calls(), 2);
+    }
+
+    #[tokio::test]
+    async fn conditional_writes_are_sent_once() {
+        let flaky = Flaky::new(1, || Kind::TimedOut);
+        let mut svc = Retry::new(flaky.clone(), policy().retry_writes(true));
+
+        let err = svc
+            .call(write().with_if_match("\"abc\""))
+            .await
+            .unwrap_err();
+        assert!(err.is_timedout());
+        assert_eq!(flaky.calls(), 1);
+
+        let flaky = Flaky::new(1, || Kind::TimedOut);
+        let mut svc = Retry::new(flaky.clone(), policy().retry_writes(true));
+        assert!(svc.call(write().with_if_none_match("*")).await.is_err());
+        assert_eq!(flaky.calls(), 1);
+
+        // reads are idempotent, conditional or not
+        let flaky = Flaky::new(1, || Kind::TimedOut);
+        let mut svc = Retry::new(flaky.clone(), policy());
+        let read = Request::new(Method::Read, "k", None).with_if_match("\"abc\"");
+        assert!(svc.call(read).await.is_ok());
+        assert_eq!(flaky.calls(), 2);
+    }
+
+    #[tokio::test]
+    async fn only_network_request_errors_are_retried() {
+        let flaky = Flaky::new(1, || Kind::Request);
+        let mut svc = Retry::new(flaky.clone(), policy());
+
+        let read = Request::new(Method::Read, "k", None);
+        assert!(svc.call(read).await.is_err());
+        assert_eq!(flaky.calls(), 1);
+    }
+
+    #[test]
+    fn backoff_is_bounded() {
+        let policy = RetryPolicy::new()
+            .base_delay(Duration::from_millis(100))
+            .max_delay(Duration::from_millis(250));
+
+        for attempt in 0..10 {
+            assert!(policy.backoff(attempt) <= Duration::from_millis(250));
+        }
+        assert!(policy.backoff(0) <= Duration::from_millis(100));
+    }
+}
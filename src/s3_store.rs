@@ -0,0 +1,211 @@
+use aws_sdk_s3::operation::get_object::GetObjectOutput;
+use aws_sdk_s3::operation::list_buckets::ListBucketsOutput;
+use aws_sdk_s3::operation::list_objects_v2::ListObjectsV2Output;
+use aws_sdk_s3::operation::put_object::PutObjectOutput;
+use aws_sdk_s3::primitives::ByteStream;
+use aws_sdk_s3::Client as S3Client;
+use bytes::Bytes;
+use futures::future::FutureExt;
+use http::header::{HeaderValue, IF_MATCH, IF_NONE_MATCH};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+
+use crate::data_file::DataFile;
+use crate::error;
+use crate::list::ListOptions;
+use crate::store::{GetOptions, ObjectStore, PutOptions, StoreFuture};
+
+/// Escaped in the `x-amz-copy-source` header; '/' separates the bucket and
+/// the key segments
+const COPY_SOURCE: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'/')
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
+
+/// The bucket, through the aws sdk
+#[derive(Debug, Clone)]
+pub struct S3Store {
+    inner: S3Client,
+    bucket: String,
+}
+
+impl S3Store {
+    pub fn new(inner: S3Client, bucket: impl Into<String>) -> Self {
+        S3Store {
+            inner,
+            bucket: bucket.into(),
+        }
+    }
+}
+
+impl ObjectStore for S3Store {
+    fn bucket(&self) -> &str {
+        &self.bucket
+    }
+
+    fn list_buckets(&self) -> StoreFuture<'_, ListBucketsOutput> {
+        async move {
+            self.inner
+                .list_buckets()
+                .send()
+                .await
+                .map_err(|sdk_err| error::from_sdk(sdk_err).with_msg("Error listing buckets"))
+        }
+        .boxed()
+    }
+
+    fn get<'a>(&'a self, key: &'a str, options: GetOptions) -> StoreFuture<'a, GetObjectOutput> {
+        async move {
+            self.inner
+                .get_object()
+                .bucket(&self.bucket)
+                .key(key)
+                .set_range(options.range.map(|range| range.to_string()))
+                .set_if_match(options.if_match)
+                .set_response_content_type(options.content_type)
+                .send()
+                .await
+                .map_err(|sdk_err| {
+                    error::from_sdk(sdk_err)
+                        .with_msg("Error reading file")
+                        .with_key(key)
+                })
+        }
+        .boxed()
+    }
+
+    fn put<'a>(
+        &'a self,
+        key: &'a str,
+        data: Bytes,
+        options: PutOptions,
+    ) -> StoreFuture<'a, PutObjectOutput> {
+        async move {
+            // the sdk has no setters for the conditional put headers
+            let conditions = [
+                (IF_MATCH, options.if_match),
+                (IF_NONE_MATCH, options.if_none_match),
+            ]
+            .into_iter()
+            .filter_map(|(name, value)| Some((name, value?)))
+            .map(|(name, value)| match HeaderValue::from_str(&value) {
+                Ok(value) => Ok((name, value)),
+                Err(_) => {
+                    Err(error::builder(format!("Invalid {}: {:?}", name, value)).with_key(key))
+                }
+            })
+            .collect::<error::Result<Vec<_>>>()?;
+            self.inner
+                .put_object()
+                .bucket(&self.bucket)
+                .key(key)
+                .set_content_type(options.content_type)
+                .body(ByteStream::from(data))
+                .customize()
+                .await
+                .map_err(|e| error::builder(e).with_key(key))?
+                .mutate_request(move |req| {
+                    for (name, value) in &conditions {
+                        req.headers_mut().insert(name, value.clone());
+                    }
+                })
+                .send()
+                .await
+                .map_err(|sdk_err| {
+                    error::from_sdk(sdk_err)
+                        .with_msg("Error writing file")
+                        .with_key(key)
+                })
+        }
+        .boxed()
+    }
+
+    fn list<'a>(
+        &'a self,
+        prefix: &'a str,
+        options: ListOptions,
+    ) -> StoreFuture<'a, ListObjectsV2Output> {
+        async move {
+            self.inner
+                .list_objects_v2()
+                .bucket(&self.bucket)
+                .prefix(prefix)
+                .set_max_keys(options.max_keys)
+                .set_start_after(options.start_after)
+                .set_delimiter(options.delimiter)
+                .set_continuation_token(options.continuation_token)
+                .send()
+                .await
+                .map_err(|sdk_err| {
+                    error::from_sdk(sdk_err)
+                        .with_msg("Error listing files")
+                        .with_key(prefix)
+                })
+        }
+        .boxed()
+    }
+
+    fn head<'a>(&'a self, key: &'a str) -> StoreFuture<'a, DataFile> {
+        async move {
+            let output = self
+                .inner
+                .head_object()
+                .bucket(&self.bucket)
+                .key(key)
+                .send()
+                .await
+                .map_err(|sdk_err| {
+                    error::from_sdk(sdk_err)
+                        .with_msg("Error reading file metadata")
+                        .with_key(key)
+                })?;
+            let mut file = DataFile::new(&self.bucket, key, output.content_length());
+            file.last_modified = output.last_modified().cloned();
+            file.etag = output.e_tag().map(str::to_string);
+            file.storage_class = output
+                .storage_class()
+                .map(|class| class.as_str().to_string());
+            Ok(file)
+        }
+        .boxed()
+    }
+
+    fn delete<'a>(&'a self, key: &'a str) -> StoreFuture<'a, ()> {
+        async move {
+            self.inner
+                .delete_object()
+                .bucket(&self.bucket)
+                .key(key)
+                .send()
+                .await
+                .map_err(|sdk_err| {
+                    error::from_sdk(sdk_err)
+                        .with_msg("Error deleting file")
+                        .with_key(key)
+                })?;
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn copy<'a>(&'a self, from: &'a str, to: &'a str) -> StoreFuture<'a, ()> {
+        async move {
+            let source = format!("{}/{}", self.bucket, utf8_percent_encode(from, COPY_SOURCE));
+            self.inner
+                .copy_object()
+                .bucket(&self.bucket)
+                .key(to)
+                .copy_source(source)
+                .send()
+                .await
+                .map_err(|sdk_err| {
+                    error::from_sdk(sdk_err)
+                        .with_msg(format!("Error copying {}", from))
+                        .with_key(to)
+                })?;
+            Ok(())
+        }
+        .boxed()
+    }
+}
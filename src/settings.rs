@@ -0,0 +1,334 @@
+//! Layered settings for the `Client`: a config file, then the selected
+//! profile in that file, then the environment, then the `ClientBuilder`
+//! overrides (the last one set wins).
+//!
+//! Config file schema (yaml; toml and json use the same keys):
+//!
+//! ```yaml
+//! bucket_name: luci-space                                   # S3_BUCKET_NAME (required)
+//! endpoint_url: https://sfo3.digitaloceanspaces.com         # S3_HOST_BASE
+//! host_bucket: https://luci-space.sfo3.digitaloceanspaces.com # S3_HOST_BUCKET
+//! region: sfo3                                              # S3_REGION_NAME
+//! force_path_style: false
+//! access_key_id: ...            # otherwise the aws default credentials chain
+//! secret_access_key: ...        # required with access_key_id
+//! session_token: ...
+//! app_name: TestAndControl
+//! etl_obj_filename: etlObj.json
+//! max_pool_connections: 17                                  # S3_MAX_POOL_CONNECTIONS
+//! connect_timeout_ms: 3100      # none unless set
+//! operation_timeout_ms: 30000
+//! test_project_id: fef57333-67c0-4825-9765-5bf48f3d5f63     # TEST_PROJECT_ID
+//!
+//! profile: prod                 # S3_PROFILE, or ClientBuilder::profile
+//! profiles:                     # any of the keys above, per provider
+//!   prod:
+//!     endpoint_url: https://sfo3.digitaloceanspaces.com
+//!     region: sfo3
+//!     bucket_name: luci-space
+//!   local:
+//!     endpoint_url: http://localhost:9000
+//!     region: us-east-1
+//!     bucket_name: luci-space
+//!     force_path_style: true
+//! ```
+//!
+//! The file is `config.{yaml,toml,json}` in the working directory when it
+//! exists, or the path in `S3_CLIENT_CONFIG`, or `ClientBuilder::config_file`.
+//!
+use config::{Config, File, FileFormat, Source};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::error::{self, Result};
+
+/// Looked up when no file is named; optional
+const DEFAULT_CONFIG_FILE: &str = "config";
+/// Names the config file
+pub(crate) const CONFIG_FILE_ENV: &str = "S3_CLIENT_CONFIG";
+/// Names the profile
+pub(crate) const PROFILE_ENV: &str = "S3_PROFILE";
+
+/// Settings key -> environment variable
+const ENV_KEYS: &[(&str, &str)] = &[
+    ("bucket_name", "S3_BUCKET_NAME"),
+    ("endpoint_url", "S3_HOST_BASE"),
+    ("host_bucket", "S3_HOST_BUCKET"),
+    ("region", "S3_REGION_NAME"),
+    ("max_pool_connections", "S3_MAX_POOL_CONNECTIONS"),
+    ("test_project_id", "TEST_PROJECT_ID"),
+];
+
+/// Every value is optional until the layers are merged; see `missing_keys`.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default)]
+pub(crate) struct Settings {
+    pub(crate) bucket_name: Option<String>,
+    pub(crate) endpoint_url: Option<String>,
+    pub(crate) host_bucket: Option<String>,
+    pub(crate) region: Option<String>,
+    pub(crate) force_path_style: Option<bool>,
+    pub(crate) access_key_id: Option<String>,
+    pub(crate) secret_access_key: Option<String>,
+    pub(crate) session_token: Option<String>,
+    pub(crate) app_name: Option<String>,
+    pub(crate) etl_obj_filename: Option<String>,
+    pub(crate) max_pool_connections: Option<usize>,
+    pub(crate) connect_timeout_ms: Option<u64>,
+    pub(crate) operation_timeout_ms: Option<u64>,
+    pub(crate) test_project_id: Option<String>,
+    /// The default profile
+    pub(crate) profile: Option<String>,
+    pub(crate) profiles: HashMap<String, Settings>,
+}
+
+impl Settings {
+    /// Read the file (when named, it must exist), apply the profile, then the
+    /// environment. The profile is `profile`, else `S3_PROFILE`, else the
+    /// `profile` key in the file; none is fine.
+    pub(crate) fn load(
+        file: Option<&Path>,
+        profile: Option<&str>,
+        env: impl Fn(&str) -> Option<String>,
+    ) -> Result<Settings> {
+        let file = match file {
+            Some(path) => File::from(path).required(true),
+            None => File::with_name(DEFAULT_CONFIG_FILE).required(false),
+        };
+        Self::layer(Self::from_source(file)?, profile, env)
+    }
+
+    /// Settings from a yaml/toml/json string, then the profile and the environment
+    #[allow(dead_code)]
+    pub(crate) fn parse(
+        s: &str,
+        format: FileFormat,
+        profile: Option<&str>,
+        env: impl Fn(&str) -> Option<String>,
+    ) -> Result<Settings> {
+        Self::layer(Self::from_source(File::from_str(s, format))?, profile, env)
+    }
+
+    fn layer(
+        file: Settings,
+        profile: Option<&str>,
+        env: impl Fn(&str) -> Option<String>,
+    ) -> Result<Settings> {
+        let profile = profile
+            .map(str::to_string)
+            .or_else(|| env(PROFILE_ENV))
+            .or_else(|| file.profile.clone());
+        let file = match profile {
+            Some(name) => file.with_profile(&name)?,
+            None => file,
+        };
+        Ok(file.merge(Self::from_env(env)?))
+    }
+
+    fn from_source<S>(source: S) -> Result<Settings>
+    where
+        S: Source + Send + Sync + 'static,
+    {
+        Config::builder()
+            .add_source(source)
+            .build()
+            .and_then(Config::try_deserialize)
+            .map_err(|e| error::builder(e).with_msg("Invalid settings"))
+    }
+
+    fn from_env(env: impl Fn(&str) -> Option<String>) -> Result<Settings> {
+        let mut builder = Config::builder();
+        for (key, var) in ENV_KEYS {
+            builder = builder
+                .set_override_option(*key, env(var))
+                .map_err(|e| error::builder(e).with_msg(format!("Invalid {}", var)))?;
+        }
+        builder
+            .build()
+            .and_then(Config::try_deserialize)
+            .map_err(|e| error::builder(e).with_msg("Invalid environment settings"))
+    }
+
+    /// Layer the named profile on top of `self`
+    pub(crate) fn with_profile(mut self, name: &str) -> Result<Settings> {
+        match self.profiles.remove(name) {
+            Some(profile) => Ok(Settings {
+                profile: Some(name.to_string()),
+                ..self.merge(profile)
+            }),
+            None => {
+                let mut available = self.profiles.keys().cloned().collect::<Vec<_>>();
+                available.sort();
+                Err(error::builder(format!("Unknown profile {}", name))
+                    .with_msg(format!("Available profiles: [{}]", available.join(", "))))
+            }
+        }
+    }
+
+    /// Layer `overrides` on top of `self`
+    pub(crate) fn merge(self, overrides: Settings) -> Settings {
+        Settings {
+            bucket_name: overrides.bucket_name.or(self.bucket_name),
+            endpoint_url: overrides.endpoint_url.or(self.endpoint_url),
+            host_bucket: overrides.host_bucket.or(self.host_bucket),
+            region: overrides.region.or(self.region),
+            force_path_style: overrides.force_path_style.or(self.force_path_style),
+            access_key_id: overrides.access_key_id.or(self.access_key_id),
+            secret_access_key: overrides.secret_access_key.or(self.secret_access_key),
+            session_token: overrides.session_token.or(self.session_token),
+            app_name: overrides.app_name.or(self.app_name),
+            etl_obj_filename: overrides.etl_obj_filename.or(self.etl_obj_filename),
+            max_pool_connections: overrides.max_pool_connections.or(self.max_pool_connections),
+            connect_timeout_ms: overrides.connect_timeout_ms.or(self.connect_timeout_ms),
+            operation_timeout_ms: overrides.operation_timeout_ms.or(self.operation_timeout_ms),
+            test_project_id: overrides.test_project_id.or(self.test_project_id),
+            profile: overrides.profile.or(self.profile),
+            profiles: self.profiles,
+        }
+    }
+
+    /// Every required key that has no value, named as in the config file
+    /// (with the environment variable when there is one)
+    pub(crate) fn missing_keys(&self) -> Vec<String> {
+        let mut missing = Vec::new();
+        if self.bucket_name.is_none() {
+            missing.push("bucket_name");
+        }
+        match (&self.access_key_id, &self.secret_access_key) {
+            (Some(_), None) => missing.push("secret_access_key"),
+            (None, Some(_)) => missing.push("access_key_id"),
+            _ => (),
+        }
+        missing
+            .into_iter()
+            .map(|key| match ENV_KEYS.iter().find(|(k, _)| *k == key) {
+                Some((_, var)) => format!("{} ({})", key, var),
+                None => key.to_string(),
+            })
+            .collect()
+    }
+
+    /// Fails with every missing key listed at once
+    pub(crate) fn validate(self) -> Result<Settings> {
+        let missing = self.missing_keys();
+        if missing.is_empty() {
+            Ok(self)
+        } else {
+            Err(error::missing_parameter(
+                missing.join(", "),
+                "Missing settings",
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn no_env(_: &str) -> Option<String> {
+        None
+    }
+
+    #[test]
+    fn env_overrides_file() {
+        let yaml = "bucket_name: from-file\nregion: sfo3\nmax_pool_connections: 17\n";
+        let env = |var: &str| (var == "S3_BUCKET_NAME").then(|| "from-env".to_string());
+
+        let settings = Settings::parse(yaml, FileFormat::Yaml, None, env).unwrap();
+
+        assert_eq!(settings.bucket_name.as_deref(), Some("from-env"));
+        assert_eq!(settings.region.as_deref(), Some("sfo3"));
+        assert_eq!(settings.max_pool_connections, Some(17));
+    }
+
+    #[test]
+    fn overrides_win() {
+        let toml = "bucket_name = \"from-file\"\nforce_path_style = true\n";
+        let settings = Settings::parse(toml, FileFormat::Toml, None, no_env).unwrap();
+        let overrides = Settings {
+            bucket_name: Some("from-builder".to_string()),
+            ..Settings::default()
+        };
+
+        let settings = settings.merge(overrides);
+
+        assert_eq!(settings.bucket_name.as_deref(), Some("from-builder"));
+        assert_eq!(settings.force_path_style, Some(true));
+    }
+
+    #[test]
+    fn lists_every_missing_key() {
+        let settings =
+            Settings::parse("access_key_id: abc\n", FileFormat::Yaml, None, no_env).unwrap();
+
+        let err = settings.validate().unwrap_err();
+
+        assert!(err.is_missing_parameter());
+        let msg = err.to_string();
+        assert!(msg.contains("bucket_name (S3_BUCKET_NAME)"), "{}", msg);
+        assert!(msg.contains("secret_access_key"), "{}", msg);
+    }
+
+    #[test]
+    fn invalid_file_is_builder_error() {
+        let err = Settings::parse(
+            "max_pool_connections: many\n",
+            FileFormat::Yaml,
+            None,
+            no_env,
+        )
+        .unwrap_err();
+        assert!(err.is_builder());
+    }
+
+    const PROFILES: &str = r#"
+bucket_name: shared
+app_name: TestAndControl
+profile: prod
+profiles:
+  prod:
+    endpoint_url: https://sfo3.digitaloceanspaces.com
+    region: sfo3
+    bucket_name: luci-space
+  local:
+    endpoint_url: http://localhost:9000
+    force_path_style: true
+"#;
+
+    #[test]
+    fn profile_from_file() {
+        let settings = Settings::parse(PROFILES, FileFormat::Yaml, None, no_env).unwrap();
+
+        assert_eq!(settings.profile.as_deref(), Some("prod"));
+        assert_eq!(settings.region.as_deref(), Some("sfo3"));
+        assert_eq!(settings.bucket_name.as_deref(), Some("luci-space"));
+        assert_eq!(settings.app_name.as_deref(), Some("TestAndControl"));
+    }
+
+    #[test]
+    fn profile_selected_by_env_then_argument() {
+        let env = |var: &str| match var {
+            "S3_PROFILE" => Some("local".to_string()),
+            "S3_BUCKET_NAME" => Some("from-env".to_string()),
+            _ => None,
+        };
+        let local = Settings::parse(PROFILES, FileFormat::Yaml, None, env).unwrap();
+        let prod = Settings::parse(PROFILES, FileFormat::Yaml, Some("prod"), env).unwrap();
+
+        assert_eq!(local.endpoint_url.as_deref(), Some("http://localhost:9000"));
+        assert_eq!(local.force_path_style, Some(true));
+        // the environment overrides the profile
+        assert_eq!(local.bucket_name.as_deref(), Some("from-env"));
+        assert_eq!(prod.region.as_deref(), Some("sfo3"));
+    }
+
+    #[test]
+    fn unknown_profile() {
+        let err = Settings::parse(PROFILES, FileFormat::Yaml, Some("staging"), no_env).unwrap_err();
+
+        assert!(err.is_builder());
+        assert!(err.to_string().contains("[local, prod]"), "{}", err);
+    }
+}
@@ -0,0 +1,503 @@
+//! Where the objects live. `Client` sends every request to an
+//! `ObjectStore`: the bucket (`S3Store`), a local directory (`LocalStore`)
+//! or memory (`MemoryStore`). The last two follow the S3 semantics of keys
+//! and listings so that project code and tests run offline unchanged.
+//!
+use std::fmt;
+use std::time::SystemTime;
+
+use aws_sdk_s3::operation::get_object::GetObjectOutput;
+use aws_sdk_s3::operation::list_buckets::ListBucketsOutput;
+use aws_sdk_s3::operation::list_objects_v2::ListObjectsV2Output;
+use aws_sdk_s3::operation::put_object::PutObjectOutput;
+use aws_sdk_s3::primitives::{ByteStream, DateTime};
+use aws_sdk_s3::types::{Bucket, CommonPrefix, Object, ObjectStorageClass};
+use bytes::Bytes;
+use futures::future::BoxFuture;
+use md5::{Digest, Md5};
+
+use crate::data_file::DataFile;
+use crate::download::ByteRange;
+use crate::error::{self, Result};
+use crate::list::ListOptions;
+
+/// S3 returns at most this many keys per page
+const MAX_KEYS: i32 = 1000;
+
+pub type StoreFuture<'a, T> = BoxFuture<'a, Result<T>>;
+
+/// The object operations of one bucket. Errors have the kind S3 would
+/// give, e.g. `Kind::NotFound` for a missing key.
+///
+/// `LocalStore` and `MemoryStore` only take keys that are also relative
+/// file paths: keys such as `dir/`, `a//b` or `a/../b`, valid in S3, are a
+/// `Kind::Builder` error there.
+pub trait ObjectStore: fmt::Debug + Send + Sync {
+    /// The bucket the keys are in
+    fn bucket(&self) -> &str;
+
+    fn list_buckets(&self) -> StoreFuture<'_, ListBucketsOutput>;
+
+    fn get<'a>(&'a self, key: &'a str, options: GetOptions) -> StoreFuture<'a, GetObjectOutput>;
+
+    fn put<'a>(
+        &'a self,
+        key: &'a str,
+        data: Bytes,
+        options: PutOptions,
+    ) -> StoreFuture<'a, PutObjectOutput>;
+
+    /// One page of the keys that start with `prefix`
+    fn list<'a>(
+        &'a self,
+        prefix: &'a str,
+        options: ListOptions,
+    ) -> StoreFuture<'a, ListObjectsV2Output>;
+
+    fn head<'a>(&'a self, key: &'a str) -> StoreFuture<'a, DataFile>;
+
+    /// Deleting a missing key is not an error
+    fn delete<'a>(&'a self, key: &'a str) -> StoreFuture<'a, ()>;
+
+    fn copy<'a>(&'a self, from: &'a str, to: &'a str) -> StoreFuture<'a, ()>;
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct GetOptions {
+    pub range: Option<ByteRange>,
+    /// Fail with `Kind::Conflict` unless the object has this ETag
+    pub if_match: Option<String>,
+    /// The `Content-Type` of the response
+    pub content_type: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PutOptions {
+    pub content_type: Option<String>,
+    /// Fail with `Kind::Conflict` unless the object has this ETag
+    pub if_match: Option<String>,
+    /// `*`: fail with `Kind::Conflict` when the object exists
+    pub if_none_match: Option<String>,
+}
+
+/// `Kind::Conflict` unless the object with `e_tag` (`None` when missing)
+/// may be overwritten
+pub(crate) fn check_put(key: &str, e_tag: Option<&str>, options: &PutOptions) -> Result<()> {
+    // `*` matches any object, but not a missing one
+    let matches = |expected: &str| e_tag.is_some() && (expected == "*" || Some(expected) == e_tag);
+    let if_match = options.if_match.as_deref().is_none_or(matches);
+    let if_none_match = options
+        .if_none_match
+        .as_deref()
+        .is_none_or(|expected| !matches(expected));
+    if if_match && if_none_match {
+        Ok(())
+    } else {
+        Err(error::conflict("PreconditionFailed", "The object has changed").with_key(key))
+    }
+}
+
+/// The ETag S3 gives an object sent in one piece: the quoted md5
+pub(crate) fn e_tag(data: &[u8]) -> String {
+    format!("\"{:x}\"", Md5::digest(data))
+}
+
+/// The keys `LocalStore` and `MemoryStore` accept: those that are also
+/// valid file paths
+pub(crate) fn validate_key(key: &str) -> Result<()> {
+    let valid = !key.is_empty()
+        && !key.starts_with('/')
+        && key
+            .split('/')
+            .all(|segment| !matches!(segment, "" | "." | ".."));
+    if valid {
+        Ok(())
+    } else {
+        Err(error::builder(format!("Invalid key: {:?}", key)).with_key(key))
+    }
+}
+
+pub(crate) fn not_found(key: &str) -> error::Error {
+    error::not_found("NoSuchKey", "The key does not exist").with_key(key)
+}
+
+/// The body and metadata of a stored object, as S3 would return them
+pub(crate) fn get_output(
+    key: &str,
+    data: Bytes,
+    file: &DataFile,
+    content_type: Option<String>,
+    options: GetOptions,
+) -> Result<GetObjectOutput> {
+    check_get(key, file, &options)?;
+    let (data, content_range) = match byte_range(key, options.range, data.len() as u64)? {
+        None => (data, None),
+        Some((range, content_range)) => (
+            data.slice(range.start as usize..range.end as usize),
+            Some(content_range),
+        ),
+    };
+    Ok(ranged_output(
+        data,
+        content_range,
+        file,
+        options.content_type.or(content_type),
+    ))
+}
+
+/// The `if_match` of a get
+pub(crate) fn check_get(key: &str, file: &DataFile, options: &GetOptions) -> Result<()> {
+    match &options.if_match {
+        Some(if_match) if file.etag.as_deref() != Some(if_match.as_str()) => {
+            Err(error::conflict("PreconditionFailed", "The ETag does not match").with_key(key))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// The bytes of a `len` bytes object that `range` asks for, with the
+/// `Content-Range` to answer with; `None` for the whole object
+pub(crate) fn byte_range(
+    key: &str,
+    range: Option<ByteRange>,
+    len: u64,
+) -> Result<Option<(std::ops::Range<u64>, String)>> {
+    match range {
+        None => Ok(None),
+        Some(range) if range.start() >= len => Err(error::response(
+            format!("{:?} of {} bytes", range, len),
+            "Invalid range",
+        )
+        .with_key(key)),
+        Some(range) => {
+            let end = range.end().map_or(len, |end| end.min(len));
+            let content_range = format!("bytes {}-{}/{}", range.start(), end - 1, len);
+            Ok(Some((range.start()..end, content_range)))
+        }
+    }
+}
+
+/// The output of a get, `data` being the bytes of `content_range`
+pub(crate) fn ranged_output(
+    data: Bytes,
+    content_range: Option<String>,
+    file: &DataFile,
+    content_type: Option<String>,
+) -> GetObjectOutput {
+    GetObjectOutput::builder()
+        .content_length(data.len() as i64)
+        .set_content_range(content_range)
+        .set_e_tag(file.etag.clone())
+        .set_last_modified(file.last_modified)
+        .set_content_type(content_type)
+        .body(ByteStream::from(data))
+        .build()
+}
+
+pub(crate) fn list_buckets_output(bucket: &str) -> ListBucketsOutput {
+    ListBucketsOutput::builder()
+        .buckets(Bucket::builder().name(bucket).build())
+        .build()
+}
+
+pub(crate) fn now() -> DateTime {
+    DateTime::from(SystemTime::now())
+}
+
+/// One page of a `ListObjectsV2` over `files`, sorted by key. The
+/// continuation token is the last key of the page.
+pub(crate) fn list_page(
+    bucket: &str,
+    files: &[DataFile],
+    prefix: &str,
+    options: ListOptions,
+) -> ListObjectsV2Output {
+    let max_keys = options.max_keys.unwrap_or(MAX_KEYS).clamp(0, MAX_KEYS) as usize;
+    let after = options
+        .continuation_token
+        .clone()
+        .or_else(|| options.start_after.clone());
+    let delimiter = options.delimiter.as_deref().filter(|d| !d.is_empty());
+
+    let mut contents = Vec::new();
+    let mut prefixes: Vec<String> = Vec::new();
+    let mut last_key = None;
+    let mut truncated = false;
+
+    let files = files
+        .iter()
+        .filter(|file| file.key.starts_with(prefix))
+        .filter(|file| {
+            after
+                .as_deref()
+                .is_none_or(|after| file.key.as_str() > after)
+        });
+    for file in files {
+        let common_prefix = delimiter.and_then(|delimiter| {
+            file.key[prefix.len()..]
+                .find(delimiter)
+                .map(|i| &file.key[..prefix.len() + i + delimiter.len()])
+        });
+        // keys under the last common prefix belong to it
+        if let Some(common_prefix) = common_prefix {
+            if prefixes.last().map(String::as_str) == Some(common_prefix) {
+                last_key = Some(file.key.clone());
+                continue;
+            }
+        }
+        if contents.len() + prefixes.len() == max_keys {
+            truncated = true;
+            break;
+        }
+        match common_prefix {
+            Some(common_prefix) => prefixes.push(common_prefix.to_string()),
+            None => contents.push(to_object(file)),
+        }
+        last_key = Some(file.key.clone());
+    }
+
+    ListObjectsV2Output::builder()
+        .name(bucket)
+        .prefix(prefix)
+        .set_delimiter(options.delimiter)
+        .set_start_after(options.start_after)
+        .set_continuation_token(options.continuation_token)
+        .max_keys(max_keys as i32)
+        .key_count((contents.len() + prefixes.len()) as i32)
+        .is_truncated(truncated)
+        .set_next_continuation_token(last_key.filter(|_| truncated))
+        .set_contents(Some(contents))
+        .set_common_prefixes(Some(
+            prefixes
+                .into_iter()
+                .map(|prefix| CommonPrefix::builder().prefix(prefix).build())
+                .collect(),
+        ))
+        .build()
+}
+
+fn to_object(file: &DataFile) -> Object {
+    Object::builder()
+        .key(&file.key)
+        .size(file.size)
+        .set_e_tag(file.etag.clone())
+        .set_last_modified(file.last_modified)
+        .storage_class(ObjectStorageClass::Standard)
+        .build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn files(keys: &[&str]) -> Vec<DataFile> {
+        keys.iter().map(|key| DataFile::new("b", *key, 1)).collect()
+    }
+
+    fn keys(page: &ListObjectsV2Output) -> Vec<&str> {
+        let files = page.contents().unwrap_or_default().iter();
+        let prefixes = page.common_prefixes().unwrap_or_default().iter();
+        files
+            .filter_map(|o| o.key())
+            .chain(prefixes.filter_map(|p| p.prefix()))
+            .collect()
+    }
+
+    #[test]
+    fn pages_with_prefixes() {
+        let files = files(&["p/a", "p/d/1", "p/d/2", "p/e", "q/a"]);
+        let options = ListOptions::new().max_keys(2).directories();
+
+        let first = list_page("b", &files, "p/", options.clone());
+        assert_eq!(keys(&first), vec!["p/a", "p/d/"]);
+        assert!(first.is_truncated());
+
+        let next = options.next_page(&first).unwrap();
+        let second = list_page("b", &files, "p/", next);
+        assert_eq!(keys(&second), vec!["p/e"]);
+        assert!(!second.is_truncated());
+    }
+
+    #[test]
+    fn start_after() {
+        let files = files(&["a", "b", "c"]);
+
+        let page = list_page("b", &files, "", ListOptions::new().start_after("a"));
+
+        assert_eq!(keys(&page), vec!["b", "c"]);
+    }
+
+    #[test]
+    fn ranges_and_etags() {
+        let data = Bytes::from_static(b"a,b\n1,2\n");
+        let mut file = DataFile::new("b", "k", data.len() as i64);
+        file.etag = Some(e_tag(&data));
+        let get = |options| get_output("k", data.clone(), &file, None, options);
+
+        let output = get(GetOptions {
+            range: Some((0..4).into()),
+            ..GetOptions::default()
+        })
+        .unwrap();
+        assert_eq!(output.content_length(), 4);
+        assert_eq!(output.content_range(), Some("bytes 0-3/8"));
+
+        assert!(get(GetOptions {
+            range: Some((8..).into()),
+            ..GetOptions::default()
+        })
+        .is_err());
+        assert!(get(GetOptions {
+            if_match: Some("\"other\"".to_string()),
+            ..GetOptions::default()
+        })
+        .unwrap_err()
+        .is_conflict());
+        assert_eq!(e_tag(b""), "\"d41d8cd98f00b204e9800998ecf8427e\"");
+    }
+
+    /// The same calls give the same results on either store
+    async fn exercise(client: crate::Client) {
+        let data = Bytes::from_static(b"a,b\n1,2\n");
+        client
+            .write("p/shared/datafiles/a.csv", data.clone(), None)
+            .await
+            .unwrap();
+        client.write("p/etlObj.json", "{}", None).await.unwrap();
+
+        assert_eq!(
+            client.read_bytes("p/shared/datafiles/a.csv").await.unwrap(),
+            data
+        );
+        assert_eq!(
+            client
+                .read_range("p/shared/datafiles/a.csv", 0..3)
+                .await
+                .unwrap(),
+            "a,b"
+        );
+        let head = client.head("p/etlObj.json").await.unwrap();
+        assert_eq!((head.size, head.display_name.as_str()), (2, "etlObj.json"));
+        assert_eq!(head.etag, Some(e_tag(b"{}")));
+        for key in ["dir/", "a//b"] {
+            let err = client.write(key, "{}", None).await.unwrap_err();
+            assert!(err.is_builder(), "{}", key);
+        }
+        let any = |key| {
+            let body = crate::Body::Bytes("{}".into());
+            crate::Request::new(crate::Method::Write(body), key, None).with_if_match("*")
+        };
+        let err = client.request(any("p/missing")).await.unwrap_err();
+        assert!(err.is_conflict(), "{:?}", err);
+        client.request(any("p/etlObj.json")).await.unwrap();
+
+        client
+            .copy("p/etlObj.json", "p/shared/etlObj.json")
+            .await
+            .unwrap();
+        let keys = client.list_keys("p/").await.unwrap();
+        assert_eq!(
+            keys,
+            vec![
+                "p/etlObj.json",
+                "p/shared/datafiles/a.csv",
+                "p/shared/etlObj.json"
+            ]
+        );
+
+        client.delete("p/etlObj.json").await.unwrap();
+        client.delete("p/etlObj.json").await.unwrap();
+        assert!(client
+            .head("p/etlObj.json")
+            .await
+            .unwrap_err()
+            .is_not_found());
+        assert!(client
+            .read_bytes("p/missing")
+            .await
+            .unwrap_err()
+            .is_not_found());
+    }
+
+    #[tokio::test]
+    async fn memory_store() {
+        let store = crate::MemoryStore::new("luci-space");
+        exercise(crate::Client::with_store(store.clone())).await;
+        assert_eq!(store.keys().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn local_store() {
+        let root = std::env::temp_dir().join(format!("s3-client-{:016x}", fastrand::u64(..)));
+        std::fs::create_dir_all(&root).unwrap();
+
+        let store = crate::LocalStore::new(&root).unwrap();
+        exercise(crate::Client::with_store(store.clone())).await;
+        assert!(root.join("p/shared/etlObj.json").is_file());
+
+        // only one of the concurrent creates wins
+        let create = || PutOptions {
+            if_none_match: Some("*".to_string()),
+            ..PutOptions::default()
+        };
+        let puts = (0..8).map(|i| store.put("p/lock", Bytes::from(vec![i]), create()));
+        let results = futures::future::join_all(puts).await;
+        assert_eq!(results.iter().filter(|result| result.is_ok()).count(), 1);
+
+        // a file written outside the store is listed without reading it
+        std::fs::write(root.join("p/outside.csv"), "a,b\n1,2\n").unwrap();
+        let client = crate::Client::with_store(store);
+        let listed = |key: &'static str| {
+            let client = client.clone();
+            async move {
+                let files = client.list_data_files("p/").await.unwrap();
+                files.into_iter().find(|file| file.key == key).unwrap()
+            }
+        };
+        assert_eq!(listed("p/outside.csv").await.etag, None);
+        let head = client.head("p/outside.csv").await.unwrap();
+        assert_eq!(head.etag, Some(e_tag(b"a,b\n1,2\n")));
+        assert_eq!(listed("p/outside.csv").await.etag, head.etag);
+        assert_eq!(
+            client.read_range("p/outside.csv", 4..7).await.unwrap(),
+            "1,2"
+        );
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn put_preconditions() {
+        let if_match = PutOptions {
+            if_match: Some("\"v1\"".to_string()),
+            ..PutOptions::default()
+        };
+        let create = PutOptions {
+            if_none_match: Some("*".to_string()),
+            ..PutOptions::default()
+        };
+
+        assert!(check_put("k", Some("\"v1\""), &if_match).is_ok());
+        assert!(check_put("k", Some("\"v2\""), &if_match)
+            .unwrap_err()
+            .is_conflict());
+        assert!(check_put("k", None, &if_match).is_err());
+        let any = PutOptions {
+            if_match: Some("*".to_string()),
+            ..PutOptions::default()
+        };
+        assert!(check_put("k", Some("\"v1\""), &any).is_ok());
+        assert!(check_put("k", None, &any).unwrap_err().is_conflict());
+        assert!(check_put("k", None, &create).is_ok());
+        assert!(check_put("k", Some("\"v1\""), &create).is_err());
+        assert!(check_put("k", None, &PutOptions::default()).is_ok());
+    }
+
+    #[test]
+    fn keys_that_are_paths() {
+        assert!(validate_key("p/shared/a.json").is_ok());
+        for key in ["", "/a", "a//b", "a/../b", "dir/"] {
+            assert!(validate_key(key).unwrap_err().is_builder(), "{}", key);
+        }
+    }
+}
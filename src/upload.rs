@@ -0,0 +1,437 @@
+//! Multipart upload of large files: the body is read part by part and the
+//! parts are sent concurrently. The first failure stops the upload, which
+//! keeps the parts that made it, so it can be resumed
+//! (`MultipartUpload::send` again, or `Client::resume_upload` from another
+//! process) or aborted. S3 takes at most 10,000 parts; `upload_file` picks
+//! a part size large enough for the file.
+//!
+//! ```no_run
+//! use s3_client::{Client, UploadOptions};
+//!
+//! # async fn run(client: Client) -> s3_client::Result<()> {
+//! let file = tokio::fs::File::open("target_list.csv").await.unwrap();
+//! let mut upload = client
+//!     .start_upload("p/shared/datafiles/target_list.csv", UploadOptions::new())
+//!     .await?;
+//! match upload.send(file).await {
+//!     Ok(e_tag) => println!("{}", e_tag),
+//!     Err(_) => upload.abort().await?,
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::pin::pin;
+
+use aws_sdk_s3::primitives::ByteStream;
+use aws_sdk_s3::types::{CompletedMultipartUpload, CompletedPart};
+use bytes::Bytes;
+use futures::stream::{self, Stream, TryStreamExt};
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tracing::warn;
+
+use crate::client::Client;
+use crate::error::{self, Result};
+
+/// S3 rejects smaller parts, except the last one
+pub const MIN_PART_SIZE: usize = 5 * 1024 * 1024;
+const PART_SIZE: usize = 8 * 1024 * 1024;
+/// S3 rejects more parts
+const MAX_PARTS: i32 = 10_000;
+
+#[derive(Debug, Clone)]
+pub struct UploadOptions {
+    part_size: usize,
+    concurrency: Option<usize>,
+    content_type: Option<String>,
+}
+
+impl Default for UploadOptions {
+    fn default() -> Self {
+        UploadOptions {
+            part_size: PART_SIZE,
+            concurrency: None,
+            content_type: None,
+        }
+    }
+}
+
+impl UploadOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// 8 MiB unless set; at least `MIN_PART_SIZE`. A resumed upload must use
+    /// the size it was started with. Reading more than 10,000 parts is a
+    /// `Kind::Builder` error (80 GB at 8 MiB).
+    pub fn part_size(mut self, part_size: usize) -> Self {
+        self.part_size = part_size;
+        self
+    }
+    /// The most parts in flight; `max_pool_connections` unless set
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = Some(concurrency);
+        self
+    }
+    pub fn content_type(mut self, content_type: impl Into<String>) -> Self {
+        self.content_type = Some(content_type.into());
+        self
+    }
+
+    fn validate(self) -> Result<Self> {
+        if self.part_size < MIN_PART_SIZE {
+            return Err(error::builder(format!(
+                "Part size {} is below the minimum of {}",
+                self.part_size, MIN_PART_SIZE
+            )));
+        }
+        Ok(self)
+    }
+}
+
+/// An upload in progress: the upload id and the parts sent so far
+#[derive(Debug)]
+pub struct MultipartUpload {
+    client: Client,
+    key: String,
+    upload_id: String,
+    part_size: usize,
+    concurrency: usize,
+    /// part number -> ETag
+    parts: BTreeMap<i32, String>,
+}
+
+impl MultipartUpload {
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+    /// What `Client::resume_upload` needs, with the key
+    pub fn upload_id(&self) -> &str {
+        &self.upload_id
+    }
+    /// The number of parts S3 has
+    pub fn parts_done(&self) -> usize {
+        self.parts.len()
+    }
+
+    /// Send the parts of `reader` that S3 does not have yet, then complete the
+    /// upload; returns the ETag of the object (the combined ETag of the
+    /// parts). When resuming, `reader` must start at the beginning of the
+    /// file; the parts already sent are read and skipped. The first error
+    /// stops the upload; the parts in flight then are dropped (S3 may still
+    /// have them, `Client::resume_upload` asks).
+    pub async fn send<R>(&mut self, reader: R) -> Result<String>
+    where
+        R: AsyncRead + Unpin + Send,
+    {
+        let this = &*self;
+        let mut sent = Vec::new();
+        let result: Result<()> = {
+            let mut uploads = pin!(parts(reader, this.part_size, MAX_PARTS)
+                .map_err(|e| e.with_key(&this.key))
+                .map_ok(|(number, data)| async move {
+                    match this.parts.contains_key(&number) {
+                        true => Ok(None),
+                        false => this.upload_part(number, data).await.map(Some),
+                    }
+                })
+                .try_buffer_unordered(this.concurrency.max(1)));
+            async {
+                while let Some(part) = uploads.try_next().await? {
+                    sent.extend(part);
+                }
+                Ok(())
+            }
+            .await
+        };
+
+        self.parts.extend(sent);
+        result?;
+        self.complete().await
+    }
+
+    /// Delete the parts; the upload id is no longer valid
+    pub async fn abort(self) -> Result<()> {
+        self.client
+            .inner
+            .abort_multipart_upload()
+            .bucket(self.client.bucket())
+            .key(&self.key)
+            .upload_id(&self.upload_id)
+            .send()
+            .await
+            .map_err(|sdk_err| {
+                error::from_sdk(sdk_err)
+                    .with_msg("Error aborting upload")
+                    .with_key(&self.key)
+            })?;
+        Ok(())
+    }
+
+    async fn upload_part(&self, number: i32, data: Bytes) -> Result<(i32, String)> {
+        let output = self
+            .client
+            .inner
+            .upload_part()
+            .bucket(self.client.bucket())
+            .key(&self.key)
+            .upload_id(&self.upload_id)
+            .part_number(number)
+            .body(ByteStream::from(data))
+            .send()
+            .await
+            .map_err(|sdk_err| {
+                error::from_sdk(sdk_err)
+                    .with_msg(format!("Error uploading part {}", number))
+                    .with_key(&self.key)
+            })?;
+        let e_tag = output.e_tag().ok_or_else(|| {
+            error::response(format!("part {}", number), "No ETag for the part").with_key(&self.key)
+        })?;
+        Ok((number, e_tag.to_string()))
+    }
+
+    async fn complete(&self) -> Result<String> {
+        let parts = self
+            .parts
+            .iter()
+            .map(|(number, e_tag)| {
+                CompletedPart::builder()
+                    .part_number(*number)
+                    .e_tag(e_tag)
+                    .build()
+            })
+            .collect::<Vec<_>>();
+        let output = self
+            .client
+            .inner
+            .complete_multipart_upload()
+            .bucket(self.client.bucket())
+            .key(&self.key)
+            .upload_id(&self.upload_id)
+            .multipart_upload(
+                CompletedMultipartUpload::builder()
+                    .set_parts(Some(parts))
+                    .build(),
+            )
+            .send()
+            .await
+            .map_err(|sdk_err| {
+                error::from_sdk(sdk_err)
+                    .with_msg("Error completing upload")
+                    .with_key(&self.key)
+            })?;
+        Ok(output.e_tag().unwrap_or_default().to_string())
+    }
+}
+
+impl Client {
+    /// Upload `reader` in parts; aborts the upload when it fails. Returns
+    /// the ETag of the object.
+    pub async fn upload<R>(
+        &self,
+        key: impl AsRef<str>,
+        reader: R,
+        options: UploadOptions,
+    ) -> Result<String>
+    where
+        R: AsyncRead + Unpin + Send,
+    {
+        let mut upload = self.start_upload(key, options).await?;
+        match upload.send(reader).await {
+            Ok(e_tag) => Ok(e_tag),
+            Err(e) => {
+                let key = upload.key.clone();
+                if let Err(abort) = upload.abort().await {
+                    warn!("Failed to abort the upload of {}: {}", key, abort);
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// `upload` the file at `path`
+    pub async fn upload_file(
+        &self,
+        key: impl AsRef<str>,
+        path: impl AsRef<Path>,
+        options: UploadOptions,
+    ) -> Result<String> {
+        let path = path.as_ref();
+        let open_error = |e| error::internal(e, format!("Error opening {}", path.display()));
+        let file = tokio::fs::File::open(path).await.map_err(open_error)?;
+        let len = file.metadata().await.map_err(open_error)?.len();
+        let part_size = part_size_for(len, options.part_size);
+        self.upload(key, file, options.part_size(part_size)).await
+    }
+
+    pub async fn start_upload(
+        &self,
+        key: impl AsRef<str>,
+        options: UploadOptions,
+    ) -> Result<MultipartUpload> {
+        let options = options.validate()?;
+        let key = key.as_ref();
+        let output = self
+            .inner
+            .create_multipart_upload()
+            .bucket(self.bucket())
+            .key(key)
+            .set_content_type(options.content_type.clone())
+            .send()
+            .await
+            .map_err(|sdk_err| {
+                error::from_sdk(sdk_err)
+                    .with_msg("Error starting upload")
+                    .with_key(key)
+            })?;
+        let upload_id = output.upload_id().ok_or_else(|| {
+            error::response("no upload id", "Error starting upload").with_key(key)
+        })?;
+        Ok(self.multipart_upload(key, upload_id, options, BTreeMap::new()))
+    }
+
+    /// Pick up an upload started earlier, with the parts S3 already has
+    pub async fn resume_upload(
+        &self,
+        key: impl AsRef<str>,
+        upload_id: impl AsRef<str>,
+        options: UploadOptions,
+    ) -> Result<MultipartUpload> {
+        let options = options.validate()?;
+        let (key, upload_id) = (key.as_ref(), upload_id.as_ref());
+        let mut parts = BTreeMap::new();
+        let mut marker = None;
+        loop {
+            let page = self
+                .inner
+                .list_parts()
+                .bucket(self.bucket())
+                .key(key)
+                .upload_id(upload_id)
+                .set_part_number_marker(marker)
+                .send()
+                .await
+                .map_err(|sdk_err| {
+                    error::from_sdk(sdk_err)
+                        .with_msg("Error listing the parts of the upload")
+                        .with_key(key)
+                })?;
+            for part in page.parts().unwrap_or_default() {
+                if let Some(e_tag) = part.e_tag() {
+                    parts.insert(part.part_number(), e_tag.to_string());
+                }
+            }
+            marker = page.next_part_number_marker().map(str::to_string);
+            if !page.is_truncated() || marker.is_none() {
+                break;
+            }
+        }
+        Ok(self.multipart_upload(key, upload_id, options, parts))
+    }
+
+    fn multipart_upload(
+        &self,
+        key: &str,
+        upload_id: &str,
+        options: UploadOptions,
+        parts: BTreeMap<i32, String>,
+    ) -> MultipartUpload {
+        MultipartUpload {
+            client: self.clone(),
+            key: key.to_string(),
+            upload_id: upload_id.to_string(),
+            part_size: options.part_size,
+            concurrency: options
+                .concurrency
+                .unwrap_or(self.config.max_pool_connections),
+            parts,
+        }
+    }
+}
+
+/// `part_size`, or larger if `len` bytes would take more than `MAX_PARTS`
+fn part_size_for(len: u64, part_size: usize) -> usize {
+    let min = len.div_ceil(MAX_PARTS as u64);
+    part_size.max(usize::try_from(min).unwrap_or(usize::MAX))
+}
+
+/// The numbered parts of `reader`; an empty reader is one empty part. A
+/// reader with more than `max_parts` parts is a `Kind::Builder` error.
+fn parts<R>(reader: R, part_size: usize, max_parts: i32) -> impl Stream<Item = Result<(i32, Bytes)>>
+where
+    R: AsyncRead + Unpin,
+{
+    stream::try_unfold((reader, 1), move |(mut reader, number)| async move {
+        let mut data = Vec::with_capacity(part_size);
+        (&mut reader)
+            .take(part_size as u64)
+            .read_to_end(&mut data)
+            .await
+            .map_err(|e| error::request(e, format!("Error reading part {}", number)))?;
+        if data.is_empty() && number > 1 {
+            return Ok(None);
+        }
+        if number > max_parts {
+            return Err(error::builder(format!(
+                "More than {} parts of {} bytes; use a larger part size",
+                max_parts, part_size
+            )));
+        }
+        Ok(Some(((number, Bytes::from(data)), (reader, number + 1))))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::stream::StreamExt;
+
+    async fn sizes(data: &[u8], part_size: usize) -> Vec<(i32, usize)> {
+        parts(data, part_size, MAX_PARTS)
+            .map_ok(|(number, data)| (number, data.len()))
+            .try_collect()
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn splits_into_parts() {
+        let data = vec![0u8; 25];
+
+        assert_eq!(sizes(&data, 10).await, vec![(1, 10), (2, 10), (3, 5)]);
+        assert_eq!(sizes(&data[..20], 10).await, vec![(1, 10), (2, 10)]);
+        assert_eq!(sizes(&[], 10).await, vec![(1, 0)]);
+    }
+
+    #[tokio::test]
+    async fn part_count_is_limited() {
+        let data = [0u8; 25];
+
+        let parts = parts(&data[..], 10, 2).collect::<Vec<_>>().await;
+
+        assert_eq!(parts.len(), 3);
+        assert!(parts[1].is_ok());
+        assert!(parts[2].as_ref().unwrap_err().is_builder());
+        assert!(sizes(&data[..20], 10).await.len() == 2);
+    }
+
+    #[test]
+    fn part_size_grows_for_large_files() {
+        assert_eq!(part_size_for(1024, PART_SIZE), PART_SIZE);
+        let len = PART_SIZE as u64 * MAX_PARTS as u64;
+        assert_eq!(part_size_for(len, PART_SIZE), PART_SIZE);
+        assert_eq!(part_size_for(len + 1, PART_SIZE), PART_SIZE + 1);
+    }
+
+    #[test]
+    fn part_size_has_a_minimum() {
+        let err = UploadOptions::new().part_size(1024).validate().unwrap_err();
+        assert!(err.is_builder());
+        assert!(UploadOptions::new()
+            .part_size(MIN_PART_SIZE)
+            .validate()
+            .is_ok());
+    }
+}
@@ -0,0 +1,246 @@
+//! The full `Client` path, through the aws sdk, against the stand-in server
+mod stand_in;
+
+use futures::TryStreamExt;
+use s3_client::{
+    Body, ListOptions, Method, Request, RetryLayer, RetryPolicy, UploadOptions, MIN_PART_SIZE,
+};
+use stand_in::{StandIn, BUCKET};
+use std::time::Duration;
+use tower_layer::Layer;
+use tower_service::Service;
+
+#[tokio::test]
+async fn list_buckets() {
+    let server = StandIn::start().await;
+    let client = server.client().await;
+
+    let buckets = match client.list_buckets().await.unwrap().into_body() {
+        Body::Buckets(output) => output,
+        other => panic!("{:?}", other),
+    };
+
+    let names = buckets
+        .buckets()
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|bucket| bucket.name())
+        .collect::<Vec<_>>();
+    assert_eq!(names, vec![BUCKET]);
+}
+
+#[tokio::test]
+async fn write_read_head_delete() {
+    let server = StandIn::start().await;
+    let client = server.client().await;
+    let key = "p/shared/datafiles/target list.csv";
+
+    let put = match client
+        .write(key, "a,b\n1,2\n", Some("text/csv".to_string()))
+        .await
+        .unwrap()
+        .into_body()
+    {
+        Body::Put(output) => output,
+        other => panic!("{:?}", other),
+    };
+
+    assert_eq!(client.read_bytes(key).await.unwrap(), "a,b\n1,2\n");
+    assert_eq!(client.read_range(key, 0..3).await.unwrap(), "a,b");
+    let head = client.head(key).await.unwrap();
+    assert_eq!(head.size, 8);
+    assert_eq!(head.etag.as_deref(), put.e_tag());
+    assert_eq!(server.store().keys(), vec![key]);
+
+    client.delete(key).await.unwrap();
+    assert!(server.store().keys().is_empty());
+}
+
+#[tokio::test]
+async fn lists_every_page() {
+    let server = StandIn::start().await;
+    let client = server.client().await;
+    let items = (0..5).map(|i| (format!("p/{}.json", i), "{}"));
+    assert!(client.put_many(items, None).await.is_ok());
+    client.write("p/shared/a.json", "{}", None).await.unwrap();
+
+    let pages = client
+        .list_pages("p/", ListOptions::new().max_keys(2).directories())
+        .try_collect::<Vec<_>>()
+        .await
+        .unwrap();
+    let keys = client.list_keys("p/").await.unwrap();
+
+    assert_eq!(pages.len(), 3);
+    assert_eq!(keys.len(), 6);
+    let files = client.list_data_files("p/shared/").await.unwrap();
+    assert_eq!(files[0].display_name, "a.json");
+}
+
+#[tokio::test]
+async fn error_kinds() {
+    let server = StandIn::start().await;
+    let client = server.client().await;
+
+    let err = client.read_bytes("p/missing.json").await.unwrap_err();
+    assert!(err.is_not_found(), "{:?}", err);
+    assert_eq!(err.status(), Some(404));
+    assert_eq!(err.code(), Some("NoSuchKey"));
+    assert_eq!(err.key(), Some("p/missing.json"));
+
+    assert!(client
+        .head("p/missing.json")
+        .await
+        .unwrap_err()
+        .is_not_found());
+
+    server.fail_next(403, "AccessDenied");
+    assert!(client
+        .read_bytes("p/a")
+        .await
+        .unwrap_err()
+        .is_unauthorized());
+}
+
+#[tokio::test]
+async fn retries_throttling() {
+    let server = StandIn::start().await;
+    let client = server.client().await;
+    client.write("p/a.json", "{}", None).await.unwrap();
+    server.fail_next(503, "SlowDown");
+    server.fail_next(503, "SlowDown");
+
+    let policy = RetryPolicy::new().base_delay(Duration::from_millis(1));
+    let mut service = RetryLayer::new(policy).layer(client.clone());
+    let response = service
+        .call(Request::new(Method::Read, "p/a.json", None))
+        .await
+        .unwrap();
+
+    assert!(matches!(response.into_body(), Body::File(_)));
+}
+
+#[tokio::test]
+async fn downloads_one_version() {
+    let server = StandIn::start().await;
+    let client = server.client().await;
+    client.write("p/a.csv", "a,b\n1,2\n", None).await.unwrap();
+
+    let mut download = client.resumable_download("p/a.csv");
+    let mut data = Vec::new();
+    download.write_to(&mut data).await.unwrap();
+    assert_eq!(data, b"a,b\n1,2\n");
+    assert!(download.is_done());
+
+    // another version than the one being downloaded
+    let req = Request::new(Method::Read, "p/a.csv", None).with_if_match("\"stale\"");
+    let err = client.request(req).await.unwrap_err();
+    assert!(err.is_conflict(), "{:?}", err);
+    assert_eq!(err.status(), Some(412));
+}
+
+#[tokio::test]
+async fn resumes_a_cut_download() {
+    let server = StandIn::start().await;
+    let client = server.client().await;
+    let data = (0..100_000).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+    let put = match client
+        .write("p/big.csv", data.clone(), None)
+        .await
+        .unwrap()
+        .into_body()
+    {
+        Body::Put(output) => output,
+        other => panic!("{:?}", other),
+    };
+    server.cut_next_body(40_000);
+
+    let mut download = client.resumable_download("p/big.csv");
+    let mut received = Vec::new();
+    let n = download.write_to(&mut received).await.unwrap();
+
+    assert_eq!(n, data.len() as u64);
+    assert_eq!(received, data);
+    let gets = server
+        .requests()
+        .into_iter()
+        .filter(|seen| seen.method == hyper::Method::GET && seen.path == "/luci-space/p/big.csv")
+        .collect::<Vec<_>>();
+    assert_eq!(gets.len(), 2, "{:?}", gets);
+    assert_eq!(
+        (gets[0].range.as_deref(), gets[0].if_match.as_deref()),
+        (None, None)
+    );
+    assert_eq!(gets[1].range.as_deref(), Some("bytes=40000-"));
+    assert_eq!(gets[1].if_match.as_deref(), put.e_tag());
+}
+
+#[tokio::test]
+async fn conditional_writes() {
+    let server = StandIn::start().await;
+    let client = server.client().await;
+    let key = "p/diamonds/etlObj.json";
+
+    let create =
+        || Request::new(Method::Write(Body::Bytes("{}".into())), key, None).with_if_none_match("*");
+    client.clone().call(create()).await.unwrap();
+    let err = client.clone().call(create()).await.unwrap_err();
+    assert!(err.is_conflict(), "{:?}", err);
+
+    let stale =
+        Request::new(Method::Write(Body::Bytes("{}".into())), key, None).with_if_match("\"stale\"");
+    let err = client.clone().call(stale).await.unwrap_err();
+    assert!(err.is_conflict(), "{:?}", err);
+
+    // never sent without its condition
+    let invalid =
+        Request::new(Method::Write(Body::Bytes("{}".into())), key, None).with_if_match("\"a\nb\"");
+    let err = client.clone().call(invalid).await.unwrap_err();
+    assert!(err.is_builder(), "{:?}", err);
+}
+
+#[tokio::test]
+async fn multipart_uploads() {
+    let server = StandIn::start().await;
+    let client = server.client().await;
+    let key = "p/shared/datafiles/big.csv";
+    // three parts, the last one short
+    let data = (0..2 * MIN_PART_SIZE + 1024)
+        .map(|i| (i % 251) as u8)
+        .collect::<Vec<_>>();
+    let options = || UploadOptions::new().part_size(MIN_PART_SIZE).concurrency(1);
+
+    client.upload(key, &data[..], options()).await.unwrap();
+    assert_eq!(client.read_bytes(key).await.unwrap(), data);
+
+    // stops at the first failed part
+    let mut upload = client.start_upload(key, options()).await.unwrap();
+    let upload_id = upload.upload_id().to_string();
+    server.fail_part(2);
+    let err = upload.send(&data[..]).await.unwrap_err();
+    assert_eq!(err.code(), Some("InvalidArgument"), "{:?}", err);
+    assert_eq!(upload.parts_done(), 1);
+    assert_eq!(server.upload_parts(&upload_id), Some(vec![1]));
+
+    let mut resumed = client
+        .resume_upload(key, &upload_id, options())
+        .await
+        .unwrap();
+    assert_eq!(resumed.parts_done(), 1);
+    let e_tag = resumed.send(&data[..]).await.unwrap();
+    assert_eq!(
+        client.head(key).await.unwrap().etag.as_deref(),
+        Some(&*e_tag)
+    );
+    assert_eq!(client.read_bytes(key).await.unwrap(), data);
+    assert_eq!(server.upload_parts(&upload_id), None);
+
+    let upload = client.start_upload(key, options()).await.unwrap();
+    let upload_id = upload.upload_id().to_string();
+    upload.abort().await.unwrap();
+    let err = client
+        .resume_upload(key, &upload_id, options())
+        .await
+        .unwrap_err();
+    assert!(err.is_not_found(), "{:?}", err);
+}
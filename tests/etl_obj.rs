@@ -0,0 +1,83 @@
+//! Every `EtlObject` in the corpus reads and writes back as the same json
+use s3_client::etl_obj::EtlObject;
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+
+fn corpus() -> Vec<(String, String)> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/etl_obj");
+    let mut files = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .map(|path| {
+            let json = fs::read_to_string(&path).unwrap();
+            (path.display().to_string(), json)
+        })
+        .collect::<Vec<_>>();
+    files.sort();
+    assert!(!files.is_empty());
+    files
+}
+
+#[test]
+fn round_trips_the_model() {
+    for (path, json) in corpus() {
+        let etl_object: EtlObject = serde_json::from_str(&json).unwrap();
+
+        let written = serde_json::to_string(&etl_object).unwrap();
+
+        let read_back: EtlObject = serde_json::from_str(&written).unwrap();
+        assert_eq!(read_back, etl_object, "{}", path);
+    }
+}
+
+#[test]
+fn round_trips_the_json() {
+    for (path, json) in corpus() {
+        let etl_object: EtlObject = serde_json::from_str(&json).unwrap();
+
+        let written = serde_json::to_value(&etl_object).unwrap();
+
+        let expected: Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(written, expected, "{}", path);
+    }
+}
+
+#[test]
+fn corpus_matches_the_schema() {
+    let schema = EtlObject::json_schema();
+    assert_eq!(schema["$schema"], "http://json-schema.org/draft-07/schema#");
+    let validator = jsonschema::JSONSchema::compile(&schema).unwrap();
+
+    for (path, json) in corpus() {
+        let doc: Value = serde_json::from_str(&json).unwrap();
+        let errors = match validator.validate(&doc) {
+            Ok(()) => Vec::new(),
+            Err(errors) => errors.map(|e| e.to_string()).collect(),
+        };
+        assert!(errors.is_empty(), "{}: {:?}", path, errors);
+    }
+}
+
+#[test]
+fn schema_checks_the_tags() {
+    let schema = EtlObject::json_schema();
+    let validator = jsonschema::JSONSchema::compile(&schema).unwrap();
+    let json = fs::read_to_string(
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/etl_obj/measurement.json"),
+    )
+    .unwrap();
+    let doc: Value = serde_json::from_str(&json).unwrap();
+    assert!(validator.is_valid(&doc));
+
+    for (tags, pointer) in [
+        ("purpose", "/etlFields/NPI Number/purpose"),
+        ("type", "/etlUnits/NRx/type"),
+        ("source-type", "/etlFields/NRx/sources/0/source-type"),
+    ] {
+        let mut wrong = doc.clone();
+        *wrong.pointer_mut(pointer).unwrap() = "unknown".into();
+        assert!(!validator.is_valid(&wrong), "{}", tags);
+    }
+}
@@ -0,0 +1,603 @@
+//! An in-process stand-in for S3: enough of the REST API (path-style) for
+//! the aws sdk to list buckets, list, get, put, head and delete objects,
+//! and to run multipart uploads. The objects live in a `MemoryStore`.
+//! Failures can be injected, and the headers of the requests are kept for
+//! the tests to check.
+//!
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::convert::Infallible;
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use aws_sdk_s3::primitives::{DateTime, DateTimeFormat};
+use bytes::Bytes;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{header, Body, Method, Request, Response, Server, StatusCode};
+use md5::{Digest, Md5};
+use percent_encoding::percent_decode_str;
+use tokio::sync::oneshot;
+
+use s3_client::{ByteRange, Client, GetOptions, ListOptions, MemoryStore, ObjectStore, PutOptions};
+
+pub const BUCKET: &str = "luci-space";
+
+/// Stops when dropped
+pub struct StandIn {
+    addr: SocketAddr,
+    state: Arc<State>,
+    shutdown: Option<oneshot::Sender<()>>,
+}
+
+struct State {
+    store: MemoryStore,
+    /// (status, code) to answer the next requests with
+    failures: Mutex<Vec<(u16, String)>>,
+    uploads: Mutex<Uploads>,
+    /// Send this many bytes of the next object body, then drop the
+    /// connection
+    cut_body: Mutex<Option<usize>>,
+    requests: Mutex<Vec<Seen>>,
+}
+
+/// A request the stand-in got
+#[derive(Debug, Clone)]
+pub struct Seen {
+    pub method: Method,
+    pub path: String,
+    pub range: Option<String>,
+    pub if_match: Option<String>,
+}
+
+#[derive(Default)]
+struct Uploads {
+    next_id: u32,
+    by_id: HashMap<String, Upload>,
+    /// Part numbers to refuse, once each
+    failing_parts: HashSet<i32>,
+}
+
+struct Upload {
+    key: String,
+    content_type: Option<String>,
+    /// part number -> (ETag, data)
+    parts: BTreeMap<i32, (String, Bytes)>,
+}
+
+impl StandIn {
+    pub async fn start() -> StandIn {
+        let state = Arc::new(State {
+            store: MemoryStore::new(BUCKET),
+            failures: Mutex::default(),
+            uploads: Mutex::default(),
+            cut_body: Mutex::default(),
+            requests: Mutex::default(),
+        });
+        let service_state = state.clone();
+        let make_service = make_service_fn(move |_| {
+            let state = service_state.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req| {
+                    let state = state.clone();
+                    async move { Ok::<_, Infallible>(state.handle(req).await) }
+                }))
+            }
+        });
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+        let addr = server.local_addr();
+        let (shutdown, stop) = oneshot::channel::<()>();
+        tokio::spawn(server.with_graceful_shutdown(async {
+            stop.await.ok();
+        }));
+        StandIn {
+            addr,
+            state,
+            shutdown: Some(shutdown),
+        }
+    }
+
+    pub fn endpoint_url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// A client of the stand-in bucket
+    pub async fn client(&self) -> Client {
+        Client::builder()
+            .bucket(BUCKET)
+            .endpoint_url(self.endpoint_url())
+            .region("us-east-1")
+            .credentials("stand-in", "stand-in", None)
+            .force_path_style(true)
+            .build()
+            .await
+            .unwrap()
+    }
+
+    pub fn store(&self) -> &MemoryStore {
+        &self.state.store
+    }
+
+    /// Answer the next request with this S3 error
+    pub fn fail_next(&self, status: u16, code: &str) {
+        self.state
+            .failures
+            .lock()
+            .unwrap()
+            .push((status, code.to_string()));
+    }
+
+    /// Send only `after` bytes of the next object body
+    pub fn cut_next_body(&self, after: usize) {
+        *self.state.cut_body.lock().unwrap() = Some(after);
+    }
+
+    /// The requests so far, in order
+    pub fn requests(&self) -> Vec<Seen> {
+        self.state.requests.lock().unwrap().clone()
+    }
+
+    /// Refuse the next upload of this part of any multipart upload (with a
+    /// 400, which the sdk does not retry)
+    pub fn fail_part(&self, number: i32) {
+        self.state
+            .uploads
+            .lock()
+            .unwrap()
+            .failing_parts
+            .insert(number);
+    }
+
+    /// The part numbers S3 has for the upload, `None` once it is completed
+    /// or aborted
+    pub fn upload_parts(&self, upload_id: &str) -> Option<Vec<i32>> {
+        let uploads = self.state.uploads.lock().unwrap();
+        let upload = uploads.by_id.get(upload_id)?;
+        Some(upload.parts.keys().copied().collect())
+    }
+}
+
+impl Drop for StandIn {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+    }
+}
+
+impl State {
+    async fn handle(&self, req: Request<Body>) -> Response<Body> {
+        let header = |name: header::HeaderName| {
+            req.headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        };
+        let seen = Seen {
+            method: req.method().clone(),
+            path: req.uri().path().to_string(),
+            range: header(header::RANGE),
+            if_match: header(header::IF_MATCH),
+        };
+        self.requests.lock().unwrap().push(seen);
+
+        let failure = {
+            let mut failures = self.failures.lock().unwrap();
+            (!failures.is_empty()).then(|| failures.remove(0))
+        };
+        if let Some((status, code)) = failure {
+            return error(status, &code, "Injected failure", req.method());
+        }
+
+        let path = percent_decode_str(req.uri().path())
+            .decode_utf8_lossy()
+            .to_string();
+        let query: HashMap<String, String> = req
+            .uri()
+            .query()
+            .map(|q| {
+                url::form_urlencoded::parse(q.as_bytes())
+                    .into_owned()
+                    .collect()
+            })
+            .unwrap_or_default();
+        let (bucket, key) = match path.trim_start_matches('/').split_once('/') {
+            Some((bucket, key)) => (bucket.to_string(), key.to_string()),
+            None => (path.trim_start_matches('/').to_string(), String::new()),
+        };
+
+        if bucket.is_empty() {
+            return match *req.method() {
+                Method::GET => self.list_buckets().await,
+                _ => error(405, "MethodNotAllowed", "", req.method()),
+            };
+        }
+        if bucket != BUCKET {
+            return error(404, "NoSuchBucket", &bucket, req.method());
+        }
+
+        let method = req.method().clone();
+        if let (false, Some(upload_id)) = (key.is_empty(), query.get("uploadId")) {
+            return match method {
+                Method::PUT => self.upload_part(upload_id, &query, req).await,
+                Method::POST => self.complete_upload(upload_id, req).await,
+                Method::GET => self.list_parts(upload_id),
+                Method::DELETE => self.abort_upload(upload_id),
+                _ => error(501, "NotImplemented", "", &method),
+            };
+        }
+        if method == Method::POST && !key.is_empty() && query.contains_key("uploads") {
+            return self.create_upload(&key, &req);
+        }
+        let result = match (&method, key.is_empty()) {
+            (&Method::GET, true) => self.list(&query).await,
+            (&Method::GET, false) => self.get(&key, &req, true).await,
+            (&Method::HEAD, false) => self.get(&key, &req, false).await,
+            (&Method::PUT, false) => self.put(&key, req).await,
+            (&Method::DELETE, false) => self.store.delete(&key).await.map(|()| {
+                Response::builder()
+                    .status(StatusCode::NO_CONTENT)
+                    .body(Body::empty())
+                    .unwrap()
+            }),
+            _ => return error(501, "NotImplemented", "", &method),
+        };
+        result.unwrap_or_else(|e| {
+            let (status, code) = if e.is_not_found() {
+                (404, "NoSuchKey")
+            } else if e.is_conflict() {
+                (412, "PreconditionFailed")
+            } else if e.is_builder() {
+                (400, "InvalidArgument")
+            } else if e.is_response() {
+                (416, "InvalidRange")
+            } else {
+                (500, "InternalError")
+            };
+            error(status, code, &e.to_string(), &method)
+        })
+    }
+
+    async fn list_buckets(&self) -> Response<Body> {
+        let mut xml =
+            String::from("<ListAllMyBucketsResult><Owner><ID>stand-in</ID></Owner><Buckets>");
+        let output = self.store.list_buckets().await.unwrap();
+        for bucket in output.buckets().unwrap_or_default() {
+            let _ = write!(
+                xml,
+                "<Bucket><Name>{}</Name><CreationDate>{}</CreationDate></Bucket>",
+                escape(bucket.name().unwrap_or_default()),
+                date(&DateTime::from_secs(1_700_000_000))
+            );
+        }
+        xml.push_str("</Buckets></ListAllMyBucketsResult>");
+        ok_xml(xml)
+    }
+
+    async fn list(&self, query: &HashMap<String, String>) -> s3_client::Result<Response<Body>> {
+        let mut options = ListOptions::new();
+        if let Some(max_keys) = query.get("max-keys").and_then(|n| n.parse().ok()) {
+            options = options.max_keys(max_keys);
+        }
+        if let Some(start_after) = query.get("start-after") {
+            options = options.start_after(start_after);
+        }
+        if let Some(delimiter) = query.get("delimiter") {
+            options = options.delimiter(delimiter);
+        }
+        // the token is the last key of the previous page
+        if let Some(token) = query.get("continuation-token") {
+            options = options.start_after(token);
+        }
+        let prefix = query.get("prefix").cloned().unwrap_or_default();
+        let page = self.store.list(&prefix, options).await?;
+
+        let mut xml = format!(
+            "<ListBucketResult><Name>{}</Name><Prefix>{}</Prefix><KeyCount>{}</KeyCount><MaxKeys>{}</MaxKeys><IsTruncated>{}</IsTruncated>",
+            BUCKET,
+            escape(&prefix),
+            page.key_count(),
+            page.max_keys(),
+            page.is_truncated()
+        );
+        if let Some(token) = page.next_continuation_token() {
+            let _ = write!(
+                xml,
+                "<NextContinuationToken>{}</NextContinuationToken>",
+                escape(token)
+            );
+        }
+        if let Some(delimiter) = query.get("delimiter") {
+            let _ = write!(xml, "<Delimiter>{}</Delimiter>", escape(delimiter));
+        }
+        for object in page.contents().unwrap_or_default() {
+            let _ = write!(
+                xml,
+                "<Contents><Key>{}</Key><LastModified>{}</LastModified><ETag>{}</ETag><Size>{}</Size><StorageClass>STANDARD</StorageClass></Contents>",
+                escape(object.key().unwrap_or_default()),
+                object.last_modified().map(date).unwrap_or_default(),
+                escape(object.e_tag().unwrap_or_default()),
+                object.size()
+            );
+        }
+        for prefix in page.common_prefixes().unwrap_or_default() {
+            let _ = write!(
+                xml,
+                "<CommonPrefixes><Prefix>{}</Prefix></CommonPrefixes>",
+                escape(prefix.prefix().unwrap_or_default())
+            );
+        }
+        xml.push_str("</ListBucketResult>");
+        Ok(ok_xml(xml))
+    }
+
+    async fn get(
+        &self,
+        key: &str,
+        req: &Request<Body>,
+        with_body: bool,
+    ) -> s3_client::Result<Response<Body>> {
+        let header = |name: header::HeaderName| {
+            req.headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        };
+        let options = GetOptions {
+            range: header(header::RANGE).and_then(|range| parse_range(&range)),
+            if_match: header(header::IF_MATCH),
+            content_type: None,
+        };
+        let partial = options.range.is_some();
+        let output = self.store.get(key, options).await?;
+
+        let mut response = Response::builder()
+            .status(if partial {
+                StatusCode::PARTIAL_CONTENT
+            } else {
+                StatusCode::OK
+            })
+            .header(header::CONTENT_LENGTH, output.content_length())
+            .header(header::ETAG, output.e_tag().unwrap_or_default());
+        if let Some(last_modified) = output.last_modified() {
+            let http_date = last_modified.fmt(DateTimeFormat::HttpDate).unwrap();
+            response = response.header(header::LAST_MODIFIED, http_date);
+        }
+        if let Some(content_type) = output.content_type() {
+            response = response.header(header::CONTENT_TYPE, content_type);
+        }
+        if let Some(content_range) = output.content_range() {
+            response = response.header(header::CONTENT_RANGE, content_range);
+        }
+        let body = match with_body {
+            true => {
+                let data = output.body.collect().await.unwrap().into_bytes();
+                match self.cut_body.lock().unwrap().take() {
+                    // the length is still that of the whole body
+                    Some(after) => {
+                        let (mut sender, body) = Body::channel();
+                        let part = data.slice(..after.min(data.len()));
+                        tokio::spawn(async move {
+                            // ends short of the length, so hyper closes
+                            // the connection
+                            let _ = sender.send_data(part).await;
+                            // once the part is written
+                            let _ = futures::future::poll_fn(|cx| sender.poll_ready(cx)).await;
+                        });
+                        body
+                    }
+                    None => Body::from(data),
+                }
+            }
+            false => Body::empty(),
+        };
+        Ok(response.body(body).unwrap())
+    }
+
+    async fn put(&self, key: &str, req: Request<Body>) -> s3_client::Result<Response<Body>> {
+        let header = |name: header::HeaderName| {
+            req.headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        };
+        let options = PutOptions {
+            content_type: header(header::CONTENT_TYPE),
+            if_match: header(header::IF_MATCH),
+            if_none_match: header(header::IF_NONE_MATCH),
+        };
+        let data: Bytes = hyper::body::to_bytes(req.into_body()).await.unwrap();
+        let output = self.store.put(key, data, options).await?;
+        Ok(Response::builder()
+            .header(header::ETAG, output.e_tag().unwrap_or_default())
+            .body(Body::empty())
+            .unwrap())
+    }
+}
+
+impl State {
+    fn create_upload(&self, key: &str, req: &Request<Body>) -> Response<Body> {
+        let content_type = req
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+        let mut uploads = self.uploads.lock().unwrap();
+        uploads.next_id += 1;
+        let upload_id = format!("upload-{}", uploads.next_id);
+        uploads.by_id.insert(
+            upload_id.clone(),
+            Upload {
+                key: key.to_string(),
+                content_type,
+                parts: BTreeMap::new(),
+            },
+        );
+        ok_xml(format!(
+            "<InitiateMultipartUploadResult><Bucket>{}</Bucket><Key>{}</Key><UploadId>{}</UploadId></InitiateMultipartUploadResult>",
+            BUCKET,
+            escape(key),
+            upload_id
+        ))
+    }
+
+    async fn upload_part(
+        &self,
+        upload_id: &str,
+        query: &HashMap<String, String>,
+        req: Request<Body>,
+    ) -> Response<Body> {
+        let method = req.method().clone();
+        let number = match query.get("partNumber").and_then(|n| n.parse::<i32>().ok()) {
+            Some(number) if (1..=10_000).contains(&number) => number,
+            _ => return error(400, "InvalidArgument", "partNumber", &method),
+        };
+        let data = hyper::body::to_bytes(req.into_body()).await.unwrap();
+        let mut uploads = self.uploads.lock().unwrap();
+        if uploads.failing_parts.remove(&number) {
+            return error(400, "InvalidArgument", "Injected part failure", &method);
+        }
+        let upload = match uploads.by_id.get_mut(upload_id) {
+            Some(upload) => upload,
+            None => return error(404, "NoSuchUpload", upload_id, &method),
+        };
+        let e_tag = format!("\"{:x}\"", Md5::digest(&data));
+        upload.parts.insert(number, (e_tag.clone(), data));
+        Response::builder()
+            .header(header::ETAG, e_tag)
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    /// Puts the listed parts, in order, as the object
+    async fn complete_upload(&self, upload_id: &str, req: Request<Body>) -> Response<Body> {
+        let method = req.method().clone();
+        let body = hyper::body::to_bytes(req.into_body()).await.unwrap();
+        let body = String::from_utf8_lossy(&body);
+        let listed = body
+            .split("<Part>")
+            .skip(1)
+            .map(|part| {
+                let number = element(part, "PartNumber").and_then(|n| n.parse::<i32>().ok());
+                let e_tag = element(part, "ETag").map(|e| e.replace("&quot;", "\""));
+                number.zip(e_tag)
+            })
+            .collect::<Option<Vec<_>>>();
+
+        let upload = match self.uploads.lock().unwrap().by_id.remove(upload_id) {
+            Some(upload) => upload,
+            None => return error(404, "NoSuchUpload", upload_id, &method),
+        };
+        let mut data = Vec::new();
+        for (number, e_tag) in listed.unwrap_or_default() {
+            match upload.parts.get(&number) {
+                Some((stored, part)) if *stored == e_tag => data.extend_from_slice(part),
+                _ => return error(400, "InvalidPart", &number.to_string(), &method),
+            }
+        }
+        let options = PutOptions {
+            content_type: upload.content_type,
+            ..PutOptions::default()
+        };
+        let output = match self.store.put(&upload.key, data.into(), options).await {
+            Ok(output) => output,
+            Err(e) => return error(500, "InternalError", &e.to_string(), &method),
+        };
+        ok_xml(format!(
+            "<CompleteMultipartUploadResult><Location>/{0}/{1}</Location><Bucket>{0}</Bucket><Key>{1}</Key><ETag>{2}</ETag></CompleteMultipartUploadResult>",
+            BUCKET,
+            escape(&upload.key),
+            escape(output.e_tag().unwrap_or_default())
+        ))
+    }
+
+    fn list_parts(&self, upload_id: &str) -> Response<Body> {
+        let uploads = self.uploads.lock().unwrap();
+        let upload = match uploads.by_id.get(upload_id) {
+            Some(upload) => upload,
+            None => return error(404, "NoSuchUpload", upload_id, &Method::GET),
+        };
+        let mut xml = format!(
+            "<ListPartsResult><Bucket>{}</Bucket><Key>{}</Key><UploadId>{}</UploadId><IsTruncated>false</IsTruncated>",
+            BUCKET,
+            escape(&upload.key),
+            upload_id
+        );
+        for (number, (e_tag, data)) in &upload.parts {
+            let _ = write!(
+                xml,
+                "<Part><PartNumber>{}</PartNumber><ETag>{}</ETag><Size>{}</Size></Part>",
+                number,
+                escape(e_tag),
+                data.len()
+            );
+        }
+        xml.push_str("</ListPartsResult>");
+        ok_xml(xml)
+    }
+
+    fn abort_upload(&self, upload_id: &str) -> Response<Body> {
+        match self.uploads.lock().unwrap().by_id.remove(upload_id) {
+            Some(_) => Response::builder()
+                .status(StatusCode::NO_CONTENT)
+                .body(Body::empty())
+                .unwrap(),
+            None => error(404, "NoSuchUpload", upload_id, &Method::DELETE),
+        }
+    }
+}
+
+/// The text of the first `<name>` element
+fn element<'a>(xml: &'a str, name: &str) -> Option<&'a str> {
+    let (_, rest) = xml.split_once(&format!("<{}>", name))?;
+    let (text, _) = rest.split_once(&format!("</{}>", name))?;
+    Some(text)
+}
+
+/// `bytes=a-b` (inclusive) or `bytes=a-`
+fn parse_range(value: &str) -> Option<ByteRange> {
+    let (start, end) = value.strip_prefix("bytes=")?.split_once('-')?;
+    let start = start.parse().ok()?;
+    let end = match end {
+        "" => None,
+        end => Some(end.parse::<u64>().ok()? + 1),
+    };
+    Some(ByteRange::new(start, end))
+}
+
+fn ok_xml(xml: String) -> Response<Body> {
+    Response::builder()
+        .header(header::CONTENT_TYPE, "application/xml")
+        .body(Body::from(format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n{}",
+            xml
+        )))
+        .unwrap()
+}
+
+fn error(status: u16, code: &str, message: &str, method: &Method) -> Response<Body> {
+    let body = match *method {
+        // no body in the response to a HEAD
+        Method::HEAD => Body::empty(),
+        _ => Body::from(format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<Error><Code>{}</Code><Message>{}</Message></Error>",
+            code,
+            escape(message)
+        )),
+    };
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/xml")
+        .body(body)
+        .unwrap()
+}
+
+fn date(date: &DateTime) -> String {
+    date.fmt(DateTimeFormat::DateTime).unwrap()
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}